@@ -0,0 +1,106 @@
+//! Measures the throughput of a server handling 10,000 concurrent requests that each take 100
+//! milliseconds to complete.
+//!
+//! Run with `cargo bench`.
+
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::server::{Handler, ServerBuilder};
+use futures::{future, BoxFuture, Future};
+use futures::sync::oneshot;
+use rmpv::Value;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Core;
+
+const CONCURRENT_REQUESTS: usize = 10_000;
+const DELAY_MS: u64 = 100;
+const ROUNDS: usize = 5;
+
+/// Answers each request after `DELAY_MS` milliseconds.
+///
+/// The responses are completed by a single timer thread, so the cost measured is that of the
+/// server and not of the handler.
+#[derive(Clone)]
+struct SlowHandler {
+    timer: mpsc::Sender<(Instant, oneshot::Sender<Result<Value, Value>>)>,
+}
+
+impl SlowHandler {
+    fn new() -> Self {
+        let (timer, deadlines) = mpsc::channel::<(Instant, oneshot::Sender<Result<Value, Value>>)>();
+        thread::spawn(move || {
+            // Every request waits for the same delay, so the deadlines arrive in order.
+            for (deadline, tx) in deadlines {
+                let now = Instant::now();
+                if deadline > now {
+                    thread::sleep(deadline - now);
+                }
+                let _ = tx.send(Ok(Value::Nil));
+            }
+        });
+        SlowHandler { timer: timer }
+    }
+}
+
+impl Handler for SlowHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.timer.send((Instant::now() + Duration::from_millis(DELAY_MS), tx));
+        Box::new(rx.map_err(|_| io::Error::new(io::ErrorKind::Other, "The timer has stopped")))
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+}
+
+/// Starts the server on its own thread and event loop, and returns its address.
+fn serve() -> SocketAddr {
+    let (addr_tx, addr_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener = ServerBuilder::new(SlowHandler::new())
+            .bind(&"127.0.0.1:0".parse().unwrap(), &handle)
+            .unwrap();
+        addr_tx.send(listener.local_addr().unwrap()).unwrap();
+        core.run(listener).unwrap();
+    });
+    addr_rx.recv().unwrap()
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn main() {
+    let addr = serve();
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+
+    println!("{} concurrent requests taking {} ms each", CONCURRENT_REQUESTS, DELAY_MS);
+    for round in 0..ROUNDS {
+        let start = Instant::now();
+        let responses: Vec<_> = (0..CONCURRENT_REQUESTS).map(|_| client.request("sleep", &[])).collect();
+        let responses = core.run(future::join_all(responses)).unwrap();
+        let elapsed = seconds(start.elapsed());
+        assert!(responses.iter().all(|r| r.is_ok()));
+        println!("round {}: {:.3} s, {:.0} requests/s",
+                 round + 1,
+                 elapsed,
+                 CONCURRENT_REQUESTS as f64 / elapsed);
+    }
+}
@@ -4,15 +4,14 @@ extern crate futures;
 extern crate rmpv;
 extern crate tokio_core;
 
-use framed_msgpack_rpc::server::{Handler, Server};
+use framed_msgpack_rpc::server::{self, Handler};
 use framed_msgpack_rpc::client::Client;
-use futures::{future, BoxFuture, Future, Stream};
+use futures::{future, BoxFuture, Future};
 use rmpv::Value;
 use std::io;
 use std::thread;
 use std::time::Duration;
 use std::net::SocketAddr;
-use tokio_core::net::TcpListener;
 use tokio_core::reactor::Core;
 
 #[derive(Clone)]
@@ -61,12 +60,8 @@ fn main() {
     thread::spawn(move || {
         let mut core = Core::new().unwrap();
         let handle = core.handle();
-        let listener = TcpListener::bind(&address, &handle).unwrap();
-        core.run(listener.incoming().for_each(|(stream, _address)| {
-            let proto = Server::new(ExampleHandler.clone(), stream);
-            handle.spawn(proto.map_err(|_| ()));
-            Ok(())
-        })).unwrap()
+        let listener = server::serve(&address, ExampleHandler, &handle).unwrap();
+        core.run(listener).unwrap()
     });
 
     // Allow some time for the server to start
@@ -83,6 +78,7 @@ fn main() {
             })
             .and_then(|client| {
                 client.request("sayHello", &["World".into()])
+                    .map_err(|e| println!("Request failed: {}", e))
                     .and_then(|response| { 
                         println!("{:?}", response); 
                         Ok(client)
@@ -90,12 +86,14 @@ fn main() {
             }) 
             .and_then(|client| {
                 client.notify("This is a notification", &[])
+                    .map_err(|e| println!("Notification failed: {}", e))
                     .and_then(|_| {
                         Ok(client)
                     })
             })
             .and_then(|client| {
                 client.request("sayGoodbye", &[])
+                    .map_err(|e| println!("Request failed: {}", e))
                     .and_then(|response| { 
                         println!("{:?}", response);
                         Ok(())
@@ -10,11 +10,10 @@ extern crate futures;
 extern crate rmpv;
 extern crate tokio_core;
 
-use framed_msgpack_rpc::server::{Handler, Server};
-use futures::{BoxFuture, future, Future, Stream};
+use framed_msgpack_rpc::server::{self, Handler};
+use futures::{BoxFuture, future};
 use rmpv::Value;
 use std::io;
-use tokio_core::net::TcpListener;
 use tokio_core::reactor::Core;
 
 #[derive(Clone)]
@@ -47,11 +46,7 @@ fn main() {
     let mut core = Core::new().unwrap();
     let address = "127.0.0.1:12345".parse().unwrap();
     let handle = core.handle();
-    let listener = TcpListener::bind(&address, &handle).unwrap();
-    core.run(listener.incoming().for_each(|(stream, _address)| {
-        let proto = Server::new(ExampleHandler.clone(), stream);
-        handle.spawn(proto.map_err(|_| ()));
-        Ok(())
-    })).unwrap()
+    let listener = server::serve(&address, ExampleHandler, &handle).unwrap();
+    core.run(listener).unwrap()
 }
 
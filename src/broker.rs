@@ -0,0 +1,244 @@
+//! Publishing notifications to the clients subscribed to a topic, across all the connections of a
+//! server.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let broker = Broker::new(Overflow::Unsubscribe(1000));
+//! let listener = server::serve(&addr, broker.handler(router), &handle)?;
+//! // A client calls `subscribe("prices")`, then:
+//! broker.publish("prices", &[Value::from(42)]);
+//! ```
+
+use futures::{future, BoxFuture, Future};
+use message::RpcError;
+use rmpv::Value;
+use server::{Handler, Notifier};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The method called by a client to subscribe to a topic, given as its only parameter.
+pub const SUBSCRIBE_METHOD: &'static str = "subscribe";
+
+/// The method called by a client to unsubscribe from a topic, given as its only parameter.
+pub const UNSUBSCRIBE_METHOD: &'static str = "unsubscribe";
+
+/// What to do with a subscriber that does not read the published notifications as fast as they
+/// are published.
+///
+/// A subscriber is slow when the number of notifications waiting to be written to its connection
+/// has reached the limit, see `Notifier::queued`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Buffers the notifications of slow subscribers without bound.
+    Buffer,
+    /// Skips slow subscribers, which miss the notifications published until they catch up.
+    Skip(usize),
+    /// Unsubscribes slow subscribers from every topic.
+    Unsubscribe(usize),
+}
+
+struct Subscriber {
+    connection: usize,
+    notifier: Notifier,
+}
+
+struct Inner {
+    overflow: Overflow,
+    topics: HashMap<String, Vec<Subscriber>>,
+}
+
+/// A registry of the topics that the clients of one or more servers are subscribed to.
+///
+/// The broker can be cloned and sent to other threads to publish notifications.
+#[derive(Clone)]
+pub struct Broker {
+    connections: Arc<AtomicUsize>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Broker {
+    /// Creates a new `Broker` that handles slow subscribers according to the `overflow` policy.
+    pub fn new(overflow: Overflow) -> Self {
+        Broker {
+            connections: Arc::new(AtomicUsize::new(0)),
+            inner: Arc::new(Mutex::new(Inner {
+                overflow: overflow,
+                topics: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Wraps a handler to handle the subscribe and unsubscribe methods of the clients of this
+    /// broker.
+    ///
+    /// The other requests and notifications are passed to the `handler`.
+    pub fn handler<H: Handler>(&self, handler: H) -> Subscriptions<H> {
+        Subscriptions {
+            broker: self.clone(),
+            connection: None,
+            handler: handler,
+        }
+    }
+
+    /// Sends a notification for the `topic` with the parameters to every subscribed connection.
+    ///
+    /// The method of the notification is the topic. Returns the number of connections the
+    /// notification was sent to. Connections are unsubscribed from every topic when they close.
+    pub fn publish(&self, topic: &str, params: &[Value]) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let overflow = inner.overflow;
+        let mut sent = 0;
+        let mut slow = Vec::new();
+        if let Some(subscribers) = inner.topics.get_mut(topic) {
+            subscribers.retain(|subscriber| {
+                let queued = subscriber.notifier.queued();
+                match overflow {
+                    Overflow::Skip(limit) if queued >= limit => {
+                        debug!("Broker: skipping slow subscriber (topic = {})", topic);
+                        return true;
+                    }
+                    Overflow::Unsubscribe(limit) if queued >= limit => {
+                        warn!("Broker: unsubscribing slow subscriber ({} notification(s) queued)", queued);
+                        slow.push(subscriber.connection);
+                        return false;
+                    }
+                    _ => {}
+                }
+                match subscriber.notifier.notify(topic, params) {
+                    Ok(()) => {
+                        sent += 1;
+                        true
+                    }
+                    Err(_) => {
+                        debug!("Broker: unsubscribing closed connection (topic = {})", topic);
+                        false
+                    }
+                }
+            });
+        }
+        for connection in slow {
+            inner.remove_connection(connection);
+        }
+        inner.topics.retain(|_, subscribers| !subscribers.is_empty());
+        sent
+    }
+
+    /// Returns the number of connections subscribed to the `topic`.
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.inner.lock().unwrap().topics.get(topic).map_or(0, |subscribers| subscribers.len())
+    }
+
+    fn subscribe(&self, topic: String, connection: usize, notifier: &Notifier) {
+        debug!("Broker: subscribe (topic = {})", topic);
+        let mut inner = self.inner.lock().unwrap();
+        let subscribers = inner.topics.entry(topic).or_insert_with(Vec::new);
+        if !subscribers.iter().any(|subscriber| subscriber.connection == connection) {
+            subscribers.push(Subscriber {
+                connection: connection,
+                notifier: notifier.clone(),
+            });
+        }
+    }
+
+    fn disconnect(&self, connection: usize) {
+        debug!("Broker: unsubscribing closed connection");
+        self.inner.lock().unwrap().remove_connection(connection);
+    }
+
+    fn unsubscribe(&self, topic: &str, connection: usize) {
+        debug!("Broker: unsubscribe (topic = {})", topic);
+        let mut inner = self.inner.lock().unwrap();
+        let empty = match inner.topics.get_mut(topic) {
+            Some(subscribers) => {
+                subscribers.retain(|subscriber| subscriber.connection != connection);
+                subscribers.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            inner.topics.remove(topic);
+        }
+    }
+}
+
+impl Inner {
+    fn remove_connection(&mut self, connection: usize) {
+        for subscribers in self.topics.values_mut() {
+            subscribers.retain(|subscriber| subscriber.connection != connection);
+        }
+        self.topics.retain(|_, subscribers| !subscribers.is_empty());
+    }
+}
+
+/// A `Handler` that handles the subscribe and unsubscribe methods of a `Broker`, see
+/// `Broker::handler`.
+#[derive(Clone)]
+pub struct Subscriptions<H> {
+    broker: Broker,
+    connection: Option<(usize, Notifier)>,
+    handler: H,
+}
+
+impl<H> Subscriptions<H> {
+    fn topic(&self, params: &[Value]) -> Result<(String, usize, Notifier), Value> {
+        let topic = match params.first().and_then(|topic| topic.as_str()) {
+            Some(topic) if params.len() == 1 => topic.to_owned(),
+            _ => return Err(RpcError::invalid_params("Invalid params: expected a topic").into()),
+        };
+        match self.connection {
+            Some((connection, ref notifier)) => Ok((topic, connection, notifier.clone())),
+            None => Err(RpcError::internal("The connection cannot receive notifications").into()),
+        }
+    }
+}
+
+impl<H> Handler for Subscriptions<H>
+    where H: Handler + 'static,
+          H::Error: Send
+{
+    type Error = H::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        let result = match method {
+            SUBSCRIBE_METHOD => {
+                self.topic(params).map(|(topic, connection, notifier)| {
+                    self.broker.subscribe(topic, connection, &notifier);
+                    Value::Nil
+                })
+            }
+            UNSUBSCRIBE_METHOD => {
+                self.topic(params).map(|(topic, connection, _)| {
+                    self.broker.unsubscribe(&topic, connection);
+                    Value::Nil
+                })
+            }
+            _ => {
+                return Box::new(self.handler
+                    .handle_request(method, params)
+                    .map(|result| result.map(|v| v.into()).map_err(|e| e.into())))
+            }
+        };
+        Box::new(future::ok(result))
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &[Value]) -> BoxFuture<(), Self::Error> {
+        self.handler.handle_notification(method, params)
+    }
+
+    fn connected(&mut self, notifier: Notifier) {
+        let connection = self.broker.connections.fetch_add(1, Ordering::SeqCst);
+        self.connection = Some((connection, notifier.clone()));
+        self.handler.connected(notifier);
+    }
+
+    fn disconnected(&mut self) {
+        if let Some((connection, _)) = self.connection.take() {
+            self.broker.disconnect(connection);
+        }
+        self.handler.disconnected();
+    }
+}
@@ -1,76 +1,459 @@
 // Portions of this were taken from the [rmp-rpc](https://github.com/little-dude/rmp-rpc) project.
 
 use codec::Codec;
-use futures::{Async, Future, Poll, Sink, Stream};
+use dispatch::{self, Dispatch, Dispatcher, Pushed};
+use futures::{future, Async, AsyncSink, Future, Poll, Sink, Stream};
+use futures::future::Shared;
+use futures::stream::FuturesUnordered;
 use futures::sync::{mpsc, oneshot};
 use message::{Message, Notification, Request};
 use rmpv::Value;
-use std::collections::HashMap;
+use rmpv::ext;
+use semaphore::{Acquire, Permit, Semaphore};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use server::Handler;
+use std::collections::{HashMap, VecDeque};
+use std::error;
+use std::fmt;
 use std::io;
+use std::marker::PhantomData;
 use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio_core::net::TcpStream;
-use tokio_core::reactor::Handle;
-use tokio_io::AsyncRead;
-use tokio_io::codec::Framed;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::codec::{Decoder, Encoder, Framed};
+#[cfg(unix)]
+use tokio_uds::UnixStream;
+
+type AckSender = oneshot::Sender<Result<(), RequestError>>;
+type ResponseSender = oneshot::Sender<Result<Result<Value, Value>, RequestError>>;
+type QueuedNotification = (Notification, AckSender);
+type QueuedRequest = (Request, ResponseSender);
+/// The senders of the streams of pushed notifications, or `None` once the connection has ended.
+type Subscribers = Arc<Mutex<Option<Vec<mpsc::UnboundedSender<Notification>>>>>;
+
+/// The default number of requests and notifications that can be queued for sending.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// The errors that can occur while sending a request or notification and waiting for the outcome.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The connection was closed by the server before the outcome was known.
+    ConnectionClosed,
+    /// The connection failed before the outcome was known.
+    Io(io::Error),
+    /// The response was not received before the deadline of the request.
+    Timeout,
+    /// The connection was dropped before the outcome was known, for example because the event
+    /// loop driving it was stopped.
+    Cancelled,
+}
+
+impl RequestError {
+    /// Copies the error so that it can be reported to every pending request.
+    fn duplicate(&self) -> Self {
+        match *self {
+            RequestError::ConnectionClosed => RequestError::ConnectionClosed,
+            RequestError::Io(ref e) => RequestError::Io(io::Error::new(e.kind(), e.to_string())),
+            RequestError::Timeout => RequestError::Timeout,
+            RequestError::Cancelled => RequestError::Cancelled,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestError::Io(ref e) => write!(f, "The connection failed ({})", e),
+            _ => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for RequestError {
+    fn description(&self) -> &str {
+        match *self {
+            RequestError::ConnectionClosed => "The connection was closed by the server",
+            RequestError::Io(_) => "The connection failed",
+            RequestError::Timeout => "The response was not received before the deadline",
+            RequestError::Cancelled => "The connection was dropped",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            RequestError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A message that waits for room in the queue of the connection before it is sent.
+struct Outgoing<T> {
+    acquire: Option<Acquire>,
+    capacity: Semaphore,
+    message: Option<T>,
+    tx: mpsc::UnboundedSender<(T, Permit)>,
+}
+
+impl<T> Outgoing<T> {
+    /// Queues the message if there is room, or if `wait` is true, schedules the current task to
+    /// be notified when there is room. The message is returned if the connection has ended.
+    fn poll_send(&mut self, wait: bool) -> Result<Async<()>, T> {
+        if self.message.is_none() {
+            return Ok(Async::Ready(()));
+        }
+        let permit = if wait {
+            if self.acquire.is_none() {
+                self.acquire = Some(self.capacity.acquire());
+            }
+            match self.acquire.as_mut().map(|acquire| acquire.poll()) {
+                Some(Async::Ready(permit)) => {
+                    self.acquire = None;
+                    permit
+                }
+                _ => return Ok(Async::NotReady),
+            }
+        } else {
+            match self.capacity.try_acquire() {
+                Some(permit) => permit,
+                None => return Ok(Async::NotReady),
+            }
+        };
+        match self.message.take() {
+            Some(message) => {
+                mpsc::UnboundedSender::send(&self.tx, (message, permit))
+                    .map(Async::Ready)
+                    .map_err(|e| e.into_inner().0)
+            }
+            None => Ok(Async::Ready(())),
+        }
+    }
+}
 
 /// A response from sending a request.
 ///
-/// Since requests expect an eventual response, a future is needed.
+/// Since requests expect an eventual response, a future is needed. Dropping the `Response`
+/// cancels the request, and the response is discarded if it arrives later, see
+/// `ClientBuilder::cancel_notification`.
+///
+/// If the queue of the connection is full, the request is sent once the `Response` is polled and
+/// there is room, see `ClientBuilder::capacity`.
+///
+/// The deadline of the request, if any, is timed from when the request is made, so it includes the
+/// time spent waiting for room in the queue and for the transport to accept the request.
 pub struct Response {
-    inner: oneshot::Receiver<Result<Value, Value>>,
+    deadline: Option<Timeout>,
+    inner: oneshot::Receiver<Result<Result<Value, Value>, RequestError>>,
+    outgoing: Outgoing<QueuedRequest>,
+}
+
+impl Response {
+    /// Indicates if the deadline of the request has passed.
+    fn poll_deadline(&mut self) -> bool {
+        match self.deadline.as_mut().map(|deadline| deadline.poll()) {
+            Some(Ok(Async::NotReady)) | None => false,
+            Some(_) => {
+                self.deadline = None;
+                true
+            }
+        }
+    }
 }
 
 impl Future for Response {
     type Item = Result<Value, Value>;
-    type Error = ();
+    type Error = RequestError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.inner.poll().map_err(|_| ())
+        match self.outgoing.poll_send(true) {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => {
+                if self.poll_deadline() {
+                    // The request was never queued, so the connection does not know about it.
+                    self.outgoing.acquire = None;
+                    self.outgoing.message = None;
+                    return Err(RequestError::Timeout);
+                }
+                return Ok(Async::NotReady);
+            }
+            Err(_) => return Err(RequestError::ConnectionClosed),
+        }
+        match self.inner.poll() {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => {
+                if self.poll_deadline() {
+                    // The endpoint forgets the request once it sees that the reply is unwanted.
+                    self.inner.close();
+                    return Err(RequestError::Timeout);
+                }
+                Ok(Async::NotReady)
+            }
+            Err(_) => Err(RequestError::Cancelled),
+        }
+    }
+}
+
+/// The errors that can occur while making a typed call, see `Client::call`.
+#[derive(Debug)]
+pub enum CallError {
+    /// The request could not be sent or the response could not be received.
+    Request(RequestError),
+    /// The server responded with an error.
+    Remote(Value),
+    /// The parameters could not be serialized.
+    Encode(ext::Error),
+    /// The result could not be deserialized into the expected type.
+    Decode(ext::Error),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallError::Request(ref e) => write!(f, "{}", e),
+            CallError::Remote(ref v) => write!(f, "The server responded with an error ({})", v),
+            CallError::Encode(ref e) => write!(f, "The parameters could not be serialized ({})", e),
+            CallError::Decode(ref e) => write!(f, "The result could not be deserialized ({})", e),
+        }
+    }
+}
+
+impl error::Error for CallError {
+    fn description(&self) -> &str {
+        match *self {
+            CallError::Request(ref e) => error::Error::description(e),
+            CallError::Remote(_) => "The server responded with an error",
+            CallError::Encode(_) => "The parameters could not be serialized",
+            CallError::Decode(_) => "The result could not be deserialized",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            CallError::Request(ref e) => Some(e),
+            CallError::Encode(ref e) | CallError::Decode(ref e) => Some(e),
+            CallError::Remote(_) => None,
+        }
+    }
+}
+
+/// A typed response from a call.
+///
+/// The result is deserialized into `R` when the response is received.
+pub struct Call<R> {
+    error: Option<CallError>,
+    response: Option<Response>,
+    result: PhantomData<fn() -> R>,
+}
+
+impl<R: DeserializeOwned> Future for Call<R> {
+    type Item = R;
+    type Error = CallError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let result = match self.response {
+            Some(ref mut response) => try_ready!(response.poll().map_err(CallError::Request)),
+            None => panic!("Call polled after completion"),
+        };
+        self.response = None;
+        match result {
+            Ok(v) => ext::from_value(v).map(Async::Ready).map_err(CallError::Decode),
+            Err(e) => Err(CallError::Remote(e)),
+        }
     }
 }
 
 /// An acknowledgement for sending a notification.
 ///
 /// Since notifications are sent to a server without expecting a response, a placeholder-like
-/// future is need for sending a notification to allow chaining methods.
+/// future is need for sending a notification to allow chaining methods. The `Ack` completes once
+/// the notification has been written to the transport.
+///
+/// If the queue of the connection is full, the notification is sent once the `Ack` is polled and
+/// there is room, see `ClientBuilder::capacity`. Dropping the `Ack` before then discards the
+/// notification.
+#[must_use = "the notification is discarded if the queue is full and the Ack is dropped"]
 pub struct Ack {
-    inner: oneshot::Receiver<()>,
+    inner: oneshot::Receiver<Result<(), RequestError>>,
+    outgoing: Outgoing<QueuedNotification>,
 }
 
 impl Future for Ack {
+    type Item = ();
+    type Error = RequestError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.outgoing.poll_send(true) {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(_) => return Err(RequestError::ConnectionClosed),
+        }
+        match self.inner.poll() {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(RequestError::Cancelled),
+        }
+    }
+}
+
+impl Drop for Ack {
+    fn drop(&mut self) {
+        if let Some((ref notification, _)) = self.outgoing.message {
+            warn!("Client: discarding notification that was waiting for room in the queue (method = {})",
+                  notification.method);
+        }
+    }
+}
+
+/// A future that completes when the connection of a `Client` has ended, see `Client::closed`.
+pub struct Closed {
+    inner: Shared<oneshot::Receiver<()>>,
+}
+
+impl Future for Closed {
     type Item = ();
     type Error = ();
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.inner.poll().map_err(|_| ())
+        match self.inner.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            // The endpoint may be dropped without completing, e.g. with its event loop.
+            Ok(Async::Ready(_)) | Err(_) => Ok(Async::Ready(())),
+        }
+    }
+}
+
+/// A stream of the notifications pushed by the server, see `Client::notifications`.
+pub struct Notifications {
+    inner: mpsc::UnboundedReceiver<Notification>,
+}
+
+impl Stream for Notifications {
+    type Item = Notification;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
     }
 }
 
 /// A client used to send requests or notifications to a `Framed-MessagePack-RPC` server.
 pub struct Client {
-    requests_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Value, Value>>)>,
-    notifications_tx: mpsc::UnboundedSender<(Notification, oneshot::Sender<()>)>,
+    capacity: Semaphore,
+    closed: Shared<oneshot::Receiver<()>>,
+    discarded_responses: Arc<AtomicUsize>,
+    handle: Handle,
+    requests_tx: mpsc::UnboundedSender<(QueuedRequest, Permit)>,
+    notifications_tx: mpsc::UnboundedSender<(QueuedNotification, Permit)>,
+    subscribers: Subscribers,
+    timeout: Option<Duration>,
 }
 
 impl Client {
     /// Send a `Framed-MessagePack-RPC` request.
+    ///
+    /// The response is subject to the default deadline of the client, if any, see
+    /// `ClientBuilder::timeout`.
     pub fn request(&self, method: &str, params: &[Value]) -> Response {
+        self.send_request(method, params, None)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request that fails with `RequestError::Timeout` if the
+    /// response is not received within the `timeout`.
+    ///
+    /// The timeout replaces the default deadline of the client, and is timed from this call, see
+    /// `Response`. A response that arrives after the deadline is discarded, see
+    /// `Client::discarded_responses`.
+    pub fn request_with_timeout(&self, method: &str, params: &[Value], timeout: Duration) -> Response {
+        self.send_request(method, params, Some(timeout))
+    }
+
+    fn send_request(&self, method: &str, params: &[Value], timeout: Option<Duration>) -> Response {
         trace!("Client: request (method = {}, params = {:?})", method, params);
         let request = Request {
             id: 0,
             method: method.to_owned(),
             params: Vec::from(params),
         };
+        let deadline = timeout.or(self.timeout).and_then(|timeout| {
+            match Timeout::new(timeout, &self.handle) {
+                Ok(deadline) => Some(deadline),
+                Err(e) => {
+                    error!("Client: failed to time the request ({})", e);
+                    None
+                }
+            }
+        });
         let (tx, rx) = oneshot::channel();
-        // If send returns an Err, its because the other side has been dropped. By ignoring it,
-        // we are just dropping the `tx`, which will mean the rx will return Canceled when
-        // polled. In turn, that is translated into a BrokenPipe, which conveys the proper
-        // error.
-        let _ = mpsc::UnboundedSender::send(&self.requests_tx, (request, tx));
-        Response { inner: rx }
+        let mut outgoing = Outgoing {
+            acquire: None,
+            capacity: self.capacity.clone(),
+            message: Some((request, tx)),
+            tx: self.requests_tx.clone(),
+        };
+        // If send returns an Err, its because the connection has already ended.
+        if let Err((_, tx)) = outgoing.poll_send(false) {
+            let _ = tx.send(Err(RequestError::ConnectionClosed));
+        }
+        Response {
+            deadline: deadline,
+            inner: rx,
+            outgoing: outgoing,
+        }
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request with typed parameters and result.
+    ///
+    /// The parameters are serialized with serde. Tuples, sequences, and structs are sent as the
+    /// list of parameters, `()` is sent as an empty list, and any other value is sent as a single
+    /// parameter. The result is deserialized into `R`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// client.call::<_, String>("sayHello", ("World",))
+    ///     .and_then(|greeting| Ok(println!("{}", greeting)))
+    /// ```
+    pub fn call<P, R>(&self, method: &str, params: P) -> Call<R>
+        where P: Serialize,
+              R: DeserializeOwned
+    {
+        match ext::to_value(params) {
+            Ok(params) => {
+                let params = match params {
+                    Value::Array(params) => params,
+                    Value::Nil => Vec::new(),
+                    param => vec![param],
+                };
+                Call {
+                    error: None,
+                    response: Some(self.request(method, &params)),
+                    result: PhantomData,
+                }
+            }
+            Err(e) => Call {
+                error: Some(CallError::Encode(e)),
+                response: None,
+                result: PhantomData,
+            },
+        }
     }
 
     /// Send a `Framed-MessagePack-RPC` notification.
+    ///
+    /// The notification is queued at once if there is room in the queue. Otherwise it is held by
+    /// the `Ack` until the `Ack` is polled and there is room, so a sender that outpaces the
+    /// transport waits on its `Ack`s instead of growing the queue, see `ClientBuilder::capacity`.
+    /// A notification still held by its `Ack` when the `Ack` is dropped is discarded.
     pub fn notify(&self, method: &str, params: &[Value]) -> Ack {
         trace!("Client: notification (method = {}, params = {:?})", method, params);
         let notification = Notification {
@@ -78,13 +461,264 @@ impl Client {
             params: Vec::from(params),
         };
         let (tx, rx) = oneshot::channel();
-        let _ = mpsc::UnboundedSender::send(&self.notifications_tx, (notification, tx));
-        Ack { inner: rx }
+        let mut outgoing = Outgoing {
+            acquire: None,
+            capacity: self.capacity.clone(),
+            message: Some((notification, tx)),
+            tx: self.notifications_tx.clone(),
+        };
+        // If send returns an Err, its because the connection has already ended.
+        if let Err((_, tx)) = outgoing.poll_send(false) {
+            let _ = tx.send(Err(RequestError::ConnectionClosed));
+        }
+        Ack {
+            inner: rx,
+            outgoing: outgoing,
+        }
+    }
+
+    /// Returns a stream of the notifications pushed by the server, see `server::Notifier`.
+    ///
+    /// Each stream receives the notifications that arrive after it was created, and ends when the
+    /// connection is closed. The notifications received by a `Peer` are passed to its handler
+    /// instead.
+    pub fn notifications(&self) -> Notifications {
+        let (tx, rx) = mpsc::unbounded();
+        // The stream is ended at once if the connection has already ended.
+        if let Some(ref mut subscribers) = *self.subscribers.lock().unwrap() {
+            subscribers.push(tx);
+        }
+        Notifications { inner: rx }
+    }
+
+    /// Returns the number of responses that were discarded because they did not match a pending
+    /// request, typically because the request had already timed out or been cancelled.
+    pub fn discarded_responses(&self) -> usize {
+        self.discarded_responses.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that completes when the connection has ended, whether it was closed by
+    /// the server or failed.
+    pub fn closed(&self) -> Closed {
+        Closed { inner: self.closed.clone() }
     }
 
     /// Connect the client to a remote `Framed-MessagePack-RPC` server.
     pub fn connect(addr: &SocketAddr, handle: &Handle) -> Connection {
+        ClientBuilder::new().connect(addr, handle)
+    }
+
+    /// Connect the client to a remote `Framed-MessagePack-RPC` server, transmitting messages with
+    /// the given codec.
+    ///
+    /// This can be used to limit the size of the frames accepted from the server, see
+    /// `CodecBuilder::max_frame_len`, or to communicate with servers that do not frame messages,
+    /// see `UnframedCodec`.
+    pub fn connect_with_codec<C>(addr: &SocketAddr, codec: C, handle: &Handle) -> Connection
+        where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+    {
+        ClientBuilder::new().codec(codec).connect(addr, handle)
+    }
+
+    /// Connect the client to a local `Framed-MessagePack-RPC` server listening on a Unix domain
+    /// socket.
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P, handle: &Handle) -> Connection {
+        ClientBuilder::new().connect_unix(path, handle)
+    }
+
+    /// Connect the client to a local `Framed-MessagePack-RPC` server listening on a Unix domain
+    /// socket, transmitting messages with the given codec.
+    #[cfg(unix)]
+    pub fn connect_unix_with_codec<P, C>(path: P, codec: C, handle: &Handle) -> Connection
+        where P: AsRef<Path>,
+              C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+    {
+        ClientBuilder::new().codec(codec).connect_unix(path, handle)
+    }
+
+    /// Creates a client that sends requests and notifications over an established transport.
+    ///
+    /// The transport can be any duplex byte stream, such as a TLS stream, a pipe, or one end of a
+    /// socket pair. The connection is driven by a task spawned on the event loop of the `handle`.
+    pub fn from_io<T>(io: T, handle: &Handle) -> Client
+        where T: AsyncRead + AsyncWrite + 'static
+    {
+        ClientBuilder::new().from_io(io, handle)
+    }
+
+    /// Creates a client that sends requests and notifications over an established transport,
+    /// transmitting messages with the given codec.
+    pub fn from_io_with_codec<T, C>(io: T, codec: C, handle: &Handle) -> Client
+        where T: AsyncRead + AsyncWrite + 'static,
+              C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+    {
+        ClientBuilder::new().codec(codec).from_io(io, handle)
+    }
+}
+
+/// A builder for a `Client`, used to configure the codec and the default deadline of requests.
+///
+/// # Example
+///
+/// ```ignore
+/// let connection = ClientBuilder::new()
+///     .timeout(Duration::from_secs(5))
+///     .connect(&addr, &handle);
+/// ```
+#[derive(Clone)]
+pub struct ClientBuilder<C = Codec> {
+    cancel_method: Option<String>,
+    capacity: usize,
+    codec: C,
+    timeout: Option<Duration>,
+}
+
+impl ClientBuilder {
+    /// Creates a new `ClientBuilder` with the default codec and without a default deadline.
+    pub fn new() -> Self {
+        ClientBuilder {
+            cancel_method: None,
+            capacity: DEFAULT_CAPACITY,
+            codec: Codec::new(),
+            timeout: None,
+        }
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder::new()
+    }
+}
+
+impl<C> ClientBuilder<C>
+    where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+{
+    /// Transmits messages with the given codec.
+    pub fn codec<C2>(self, codec: C2) -> ClientBuilder<C2> {
+        ClientBuilder {
+            cancel_method: self.cancel_method,
+            capacity: self.capacity,
+            codec: codec,
+            timeout: self.timeout,
+        }
+    }
+
+    /// Sets the number of requests and notifications that can be queued for sending.
+    ///
+    /// Messages are taken from the queue only as fast as the transport accepts them. Once the
+    /// queue is full, a new request or notification is held by its `Response` or `Ack` until it is
+    /// polled and there is room. The default capacity is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "The capacity must be at least one");
+        self.capacity = capacity;
+        self
+    }
+
+    /// Notifies the server when a request is abandoned.
+    ///
+    /// A request is abandoned when its `Response` is dropped or its deadline passes. The server is
+    /// then sent a notification for the `method` with the ID of the request as its only
+    /// parameter, so it can stop working on a result that will be discarded anyway.
+    pub fn cancel_notification(mut self, method: &str) -> Self {
+        self.cancel_method = Some(method.to_owned());
+        self
+    }
+
+    /// Sets the default deadline of requests.
+    ///
+    /// A request that has not received a response within the `timeout` of being made fails with
+    /// `RequestError::Timeout`, even if it is still waiting to be sent. The deadline can be overridden for an individual request with
+    /// `Client::request_with_timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connect the client to a remote `Framed-MessagePack-RPC` server.
+    pub fn connect(self, addr: &SocketAddr, handle: &Handle) -> Connection {
         trace!("Client: trying to connect to {}", addr);
+        self.connect_io(TcpStream::connect(addr, handle), handle)
+    }
+
+    /// Connect the client to a local `Framed-MessagePack-RPC` server listening on a Unix domain
+    /// socket.
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(self, path: P, handle: &Handle) -> Connection {
+        trace!("Client: trying to connect to {}", path.as_ref().display());
+        self.connect_io(future::result(UnixStream::connect(path, handle)), handle)
+    }
+
+    /// Creates a client that sends requests and notifications over an established transport.
+    pub fn from_io<T>(self, io: T, handle: &Handle) -> Client
+        where T: AsyncRead + AsyncWrite + 'static
+    {
+        self.spawn(io, None, None, handle)
+    }
+
+    /// Creates a `Peer` that sends requests and notifications over an established transport and
+    /// handles the ones it receives with the `handler`.
+    ///
+    /// The handler is given a `Notifier` for the connection, see `Handler::connected`.
+    pub fn peer<T, H>(self, io: T, mut handler: H, handle: &Handle) -> Peer
+        where T: AsyncRead + AsyncWrite + 'static,
+              H: Handler + 'static
+    {
+        let (notifier, pushed) = dispatch::notifier();
+        handler.connected(notifier);
+        let dispatcher: Box<Dispatch> = Box::new(Dispatcher::new(handler));
+        Peer { client: self.spawn(io, Some(dispatcher), Some(pushed), handle) }
+    }
+
+    fn spawn<T>(self, io: T, dispatcher: Option<Box<Dispatch>>, pushed: Option<Pushed>, handle: &Handle) -> Client
+        where T: AsyncRead + AsyncWrite + 'static
+    {
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        let (notifications_tx, notifications_rx) = mpsc::unbounded();
+        let discarded_responses = Arc::new(AtomicUsize::new(0));
+        let (closed_tx, closed_rx) = oneshot::channel();
+        let capacity = Semaphore::new(self.capacity);
+        let subscribers = Arc::new(Mutex::new(Some(Vec::new())));
+        let endpoint = Endpoint {
+            cancel_method: self.cancel_method,
+            closed_tx: Some(closed_tx),
+            discarded_responses: discarded_responses.clone(),
+            dispatcher: dispatcher,
+            pushed: pushed,
+            request_id: 0,
+            unsent: VecDeque::new(),
+            watches: FuturesUnordered::new(),
+            io: io.framed(self.codec),
+            requests_closed: false,
+            requests_rx: requests_rx,
+            notifications_closed: false,
+            notifications_rx: notifications_rx,
+            pending_requests: HashMap::new(),
+            pending_notifications: Vec::new(),
+            subscribers: subscribers.clone(),
+        };
+        handle.spawn(endpoint.map_err(|e| error!("Client: connection failed ({})", e)));
+        Client {
+            capacity: capacity,
+            closed: closed_rx.shared(),
+            discarded_responses: discarded_responses,
+            handle: handle.clone(),
+            requests_tx: requests_tx,
+            notifications_tx: notifications_tx,
+            subscribers: subscribers,
+            timeout: self.timeout,
+        }
+    }
+
+    fn connect_io<F, T>(self, io: F, handle: &Handle) -> Connection
+        where F: Future<Item = T, Error = io::Error> + 'static,
+              T: AsyncRead + AsyncWrite + 'static
+    {
         let (client_tx, client_rx) = oneshot::channel();
         let (error_tx, error_rx) = oneshot::channel();
 
@@ -95,34 +729,16 @@ impl Client {
             error_chan_cancelled: false,
         };
 
-        let client = TcpStream::connect(addr, handle)
-            .and_then(|stream| {
+        let endpoint_handle = handle.clone();
+        let client = io
+            .map(move |stream| {
                 trace!("Client: connection established");
-                let (requests_tx, requests_rx) = mpsc::unbounded();
-                let (notifications_tx, notifications_rx) = mpsc::unbounded();
-                let client = Client {
-                    requests_tx: requests_tx,
-                    notifications_tx: notifications_tx,
-                };
-                if client_tx.send(client).is_err() {
-                    panic!("Failed to send client to connection");
-                }
-                Endpoint {
-                    request_id: 0,
-                    shutdown: false,
-                    io: stream.framed(Codec::new()),
-                    requests_rx: requests_rx,
-                    notifications_rx: notifications_rx,
-                    pending_requests: HashMap::new(),
-                    pending_notifications: Vec::new(),
-                }
+                // If the connection has been dropped, so is the client, which closes the endpoint.
+                let _ = client_tx.send(self.from_io(stream, &endpoint_handle));
             })
-            .or_else(|e| {
-                error!("Client: connection failed");
-                if let Err(e) = error_tx.send(e) {
-                    panic!("Failed to send client to connection: {:?}", e);
-                }
-                Err(())
+            .map_err(|e| {
+                error!("Client: connection failed ({})", e);
+                let _ = error_tx.send(e);
             });
         handle.spawn(client);
         connection
@@ -141,116 +757,436 @@ impl Future for Client {
 impl Clone for Client {
     fn clone(&self) -> Self {
         Client {
+            capacity: self.capacity.clone(),
+            closed: self.closed.clone(),
+            discarded_responses: self.discarded_responses.clone(),
+            handle: self.handle.clone(),
             requests_tx: self.requests_tx.clone(),
             notifications_tx: self.notifications_tx.clone(),
+            subscribers: self.subscribers.clone(),
+            timeout: self.timeout,
         }
     }
 }
 
+/// A peer on a connection where both ends send requests and notifications to each other.
+///
+/// The requests and notifications received from the other end are passed to a `Handler`, and
+/// its responses are sent back over the same connection. The connection is served until it is
+/// closed by the other end, or until every `Peer` and `Client` handle, and every `Response` and
+/// `Ack`, has been dropped. In the latter case, the requests already received are answered
+/// before the connection is closed.
+#[derive(Clone)]
+pub struct Peer {
+    client: Client,
+}
+
+impl Peer {
+    /// Creates a peer that communicates over an established transport and handles the requests
+    /// and notifications it receives with the `handler`.
+    ///
+    /// The connection is driven by a task spawned on the event loop of the `handle`.
+    pub fn new<T, H>(handler: H, io: T, handle: &Handle) -> Peer
+        where T: AsyncRead + AsyncWrite + 'static,
+              H: Handler + 'static
+    {
+        ClientBuilder::new().peer(io, handler, handle)
+    }
+
+    /// Creates a peer that communicates over an established transport, transmitting messages
+    /// with the given codec.
+    pub fn with_codec<T, H, C>(handler: H, io: T, codec: C, handle: &Handle) -> Peer
+        where T: AsyncRead + AsyncWrite + 'static,
+              H: Handler + 'static,
+              C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+    {
+        ClientBuilder::new().codec(codec).peer(io, handler, handle)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request to the other end, see `Client::request`.
+    pub fn request(&self, method: &str, params: &[Value]) -> Response {
+        self.client.request(method, params)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request to the other end with a deadline, see
+    /// `Client::request_with_timeout`.
+    pub fn request_with_timeout(&self, method: &str, params: &[Value], timeout: Duration) -> Response {
+        self.client.request_with_timeout(method, params, timeout)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request with typed parameters and result to the other
+    /// end, see `Client::call`.
+    pub fn call<P, R>(&self, method: &str, params: P) -> Call<R>
+        where P: Serialize,
+              R: DeserializeOwned
+    {
+        self.client.call(method, params)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` notification to the other end.
+    pub fn notify(&self, method: &str, params: &[Value]) -> Ack {
+        self.client.notify(method, params)
+    }
+
+    /// Returns a `Client` that sends requests and notifications to the other end.
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+}
+
+/// How a request that was waiting for its response ended, see `Watch`.
+enum Outcome {
+    /// The response was passed to the `Response`.
+    Delivered,
+    /// The response arrived after the `Response` was dropped.
+    Discarded,
+    /// The `Response` was dropped, or its deadline passed, before the response arrived.
+    Cancelled,
+}
+
+/// Waits for the response to a request, or for the `Response` to give up on it, whichever comes
+/// first.
+///
+/// Each request is watched by its own future, so that only the requests that have been notified
+/// are polled when the endpoint wakes up.
+struct Watch {
+    id: u64,
+    reply: oneshot::Receiver<Result<Result<Value, Value>, RequestError>>,
+    response_sender: Option<ResponseSender>,
+}
+
+impl Future for Watch {
+    type Item = (u64, Outcome);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response_sender = match self.response_sender.take() {
+            Some(response_sender) => response_sender,
+            None => panic!("Watch polled after completion"),
+        };
+        let outcome = match self.reply.poll() {
+            Ok(Async::Ready(result)) => {
+                match response_sender.send(result) {
+                    Ok(()) => Outcome::Delivered,
+                    Err(_) => Outcome::Discarded,
+                }
+            }
+            // The endpoint forgot the request without replying.
+            Err(_) => Outcome::Cancelled,
+            Ok(Async::NotReady) => {
+                match response_sender.poll_cancel() {
+                    Ok(Async::Ready(())) | Err(()) => Outcome::Cancelled,
+                    Ok(Async::NotReady) => {
+                        self.response_sender = Some(response_sender);
+                        return Ok(Async::NotReady);
+                    }
+                }
+            }
+        };
+        Ok(Async::Ready((self.id, outcome)))
+    }
+}
+
 /// An endpoint to a connection with a `Framed-Msgpack-RPC` server.
-struct Endpoint {
-    notifications_rx: mpsc::UnboundedReceiver<(Notification, oneshot::Sender<()>)>,
-    pending_notifications: Vec<oneshot::Sender<()>>,
-    pending_requests: HashMap<u32, oneshot::Sender<Result<Value, Value>>>,
+///
+/// The endpoint of a `Peer` also passes the requests and notifications it receives to a handler.
+struct Endpoint<T, C> {
+    cancel_method: Option<String>,
+    closed_tx: Option<oneshot::Sender<()>>,
+    discarded_responses: Arc<AtomicUsize>,
+    dispatcher: Option<Box<Dispatch>>,
+    notifications_closed: bool,
+    notifications_rx: mpsc::UnboundedReceiver<(QueuedNotification, Permit)>,
+    pending_notifications: Vec<AckSender>,
+    /// The senders of the replies to the requests waiting for their response, by ID.
+    pending_requests: HashMap<u64, ResponseSender>,
+    /// The notifications pushed by the handler of a `Peer`, until it drops every `Notifier`.
+    pushed: Option<Pushed>,
     request_id: u32,
-    requests_rx: mpsc::UnboundedReceiver<(Request, oneshot::Sender<Result<Value, Value>>)>,
-    shutdown: bool,
-    io: Framed<TcpStream, Codec>,
+    requests_closed: bool,
+    requests_rx: mpsc::UnboundedReceiver<(QueuedRequest, Permit)>,
+    subscribers: Subscribers,
+    unsent: VecDeque<Message>,
+    watches: FuturesUnordered<Watch>,
+    io: Framed<T, C>,
 }
 
-impl Endpoint {
+impl<T, C> Endpoint<T, C>
+    where T: AsyncRead + AsyncWrite,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error>
+{
     fn handle_msg(&mut self, msg: Message) {
         match msg {
-            Message::Request(_) | Message::Notification(_) => {},
+            Message::Request(request) => {
+                match self.dispatcher {
+                    Some(ref mut dispatcher) => dispatcher.handle_request(request),
+                    None => debug!("Client: discarding request (id = {})", request.id),
+                }
+            }
+            Message::Notification(notification) => {
+                match self.dispatcher {
+                    Some(ref mut dispatcher) => dispatcher.handle_notification(notification),
+                    None => {
+                        let mut subscribers = self.subscribers.lock().unwrap();
+                        match *subscribers {
+                            Some(ref mut subscribers) if !subscribers.is_empty() => {
+                                subscribers.retain(|tx| mpsc::UnboundedSender::send(tx, notification.clone()).is_ok());
+                            }
+                            _ => debug!("Client: discarding notification (method = {})", notification.method),
+                        }
+                    }
+                }
+            }
             Message::Response(response) => {
-                if let Some(response_sender) = self.pending_requests.remove(&response.id) {
-                    response_sender.send(response.result).unwrap();
-                } 
+                let delivered = match self.pending_requests.remove(&response.id) {
+                    Some(reply) => reply.send(Ok(response.result)).is_ok(),
+                    None => false,
+                };
+                if !delivered {
+                    debug!("Client: discarding response to a request that is not pending (id = {})",
+                           response.id);
+                    self.discarded_responses.fetch_add(1, Ordering::SeqCst);
+                }
             }
         }
     }
 
-    fn process_notifications(&mut self) {
-        loop {
-            match self.notifications_rx.poll().unwrap() {
-                Async::Ready(Some((notification, ack_sender))) => {
-                    let send_task = self.io
-                        .start_send(Message::Notification(notification))
-                        .unwrap();
-                    if !send_task.is_ready() {
-                        panic!("the sink is full")
-                    }
+    fn process_notifications(&mut self) -> io::Result<()> {
+        while !self.notifications_closed && self.unsent.is_empty() {
+            match self.notifications_rx.poll() {
+                // The permit is released as soon as the notification leaves the queue.
+                Ok(Async::Ready(Some(((notification, ack_sender), _)))) => {
+                    self.send(Message::Notification(notification))?;
                     self.pending_notifications.push(ack_sender);
                 }
-                Async::Ready(None) => {
-                    self.shutdown = true;
-                    return;
+                Ok(Async::Ready(None)) | Err(()) => self.notifications_closed = true,
+                Ok(Async::NotReady) => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn process_requests(&mut self) -> io::Result<()> {
+        while !self.requests_closed && self.unsent.is_empty() {
+            match self.requests_rx.poll() {
+                Ok(Async::Ready(Some(((mut request, response_sender), _)))) => {
+                    // IDs are limited to 32-bit unsigned integers by the specifications.
+                    self.request_id = self.request_id.wrapping_add(1);
+                    request.id = self.request_id as u64;
+                    self.send(Message::Request(request))?;
+                    let (reply, reply_rx) = oneshot::channel();
+                    self.pending_requests.insert(self.request_id as u64, reply);
+                    self.watches.push(Watch {
+                        id: self.request_id as u64,
+                        reply: reply_rx,
+                        response_sender: Some(response_sender),
+                    });
                 }
-                Async::NotReady => return,
+                Ok(Async::Ready(None)) | Err(()) => self.requests_closed = true,
+                Ok(Async::NotReady) => break,
             }
         }
+        Ok(())
     }
 
-    fn process_requests(&mut self) {
+    /// Sends the responses to the requests the handler has completed.
+    fn process_responses(&mut self) -> io::Result<()> {
+        if let Some(ref mut dispatcher) = self.dispatcher {
+            dispatcher.poll_notifications();
+        }
+        while self.unsent.is_empty() {
+            let response = match self.dispatcher {
+                Some(ref mut dispatcher) => dispatcher.poll_response(),
+                None => None,
+            };
+            match response {
+                Some(response) => self.send(Message::Response(response))?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the notifications pushed by the handler, see `Handler::connected`.
+    fn process_pushed(&mut self) -> io::Result<()> {
+        while self.unsent.is_empty() {
+            let notification = match self.pushed.as_mut().map(|pushed| pushed.poll()) {
+                Some(Ok(Async::Ready(Some(notification)))) => notification,
+                Some(Ok(Async::Ready(None))) | Some(Err(())) => {
+                    self.pushed = None;
+                    break;
+                }
+                Some(Ok(Async::NotReady)) | None => break,
+            };
+            self.send(Message::Notification(notification))?;
+        }
+        Ok(())
+    }
+
+    /// Removes the requests whose `Response` has timed out or been dropped.
+    fn remove_stale_requests(&mut self) -> io::Result<()> {
         loop {
-            match self.requests_rx.poll().unwrap() {
-                Async::Ready(Some((mut request, response_sender))) => {
-                    self.request_id += 1;
-                    request.id = self.request_id;
-                    let send_task = self.io.start_send(Message::Request(request)).unwrap();
-                    if !send_task.is_ready() {
-                        panic!("the sink is full")
-                    }
-                    self.pending_requests
-                        .insert(self.request_id, response_sender);
+            let (id, outcome) = match self.watches.poll() {
+                Ok(Async::Ready(Some(watched))) => watched,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => return Ok(()),
+            };
+            match outcome {
+                Outcome::Delivered => {}
+                Outcome::Discarded => {
+                    debug!("Client: discarding response to a request that is not pending (id = {})", id);
+                    self.discarded_responses.fetch_add(1, Ordering::SeqCst);
                 }
-                Async::Ready(None) => {
-                    self.shutdown = true;
-                    return;
+                Outcome::Cancelled => {
+                    if self.pending_requests.remove(&id).is_some() {
+                        debug!("Client: request cancelled (id = {})", id);
+                        self.send_cancel(id)?;
+                    }
                 }
-                Async::NotReady => return,
             }
         }
     }
 
-    fn flush(&mut self) {
-        if self.io.poll_complete().unwrap().is_ready() {
+    fn send_cancel(&mut self, id: u64) -> io::Result<()> {
+        let notification = match self.cancel_method {
+            Some(ref method) => Notification {
+                method: method.clone(),
+                params: vec![Value::from(id)],
+            },
+            None => return Ok(()),
+        };
+        self.send(Message::Notification(notification))
+    }
+
+    /// Sends a message, queueing it if the transport is not ready to accept it.
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        if !self.unsent.is_empty() {
+            self.unsent.push_back(msg);
+            return Ok(());
+        }
+        if let AsyncSink::NotReady(msg) = self.io.start_send(msg)? {
+            self.unsent.push_back(msg);
+        }
+        Ok(())
+    }
+
+    /// Fails every pending and queued request and notification with the error.
+    ///
+    /// The streams of pushed notifications are ended as well.
+    fn fail_pending(&mut self, error: RequestError) {
+        *self.subscribers.lock().unwrap() = None;
+        for (_, reply) in self.pending_requests.drain() {
+            let _ = reply.send(Err(error.duplicate()));
+        }
+        // The watches pass the errors on to the `Response`s.
+        while let Ok(Async::Ready(Some(_))) = self.watches.poll() {}
+        for ack_sender in self.pending_notifications.drain(..) {
+            let _ = ack_sender.send(Err(error.duplicate()));
+        }
+        self.requests_rx.close();
+        while let Ok(Async::Ready(Some(((_, response_sender), _)))) = self.requests_rx.poll() {
+            let _ = response_sender.send(Err(error.duplicate()));
+        }
+        self.notifications_rx.close();
+        while let Ok(Async::Ready(Some(((_, ack_sender), _)))) = self.notifications_rx.poll() {
+            let _ = ack_sender.send(Err(error.duplicate()));
+        }
+    }
+
+    /// Sends the queued messages and flushes the transport.
+    ///
+    /// The notifications are acknowledged once they have all been written. Returns `true` once
+    /// every message has been written.
+    fn flush(&mut self) -> io::Result<bool> {
+        while let Some(msg) = self.unsent.pop_front() {
+            if let AsyncSink::NotReady(msg) = self.io.start_send(msg)? {
+                self.unsent.push_front(msg);
+                break;
+            }
+        }
+        if self.io.poll_complete()?.is_ready() && self.unsent.is_empty() {
             for ack_sender in self.pending_notifications.drain(..) {
-                ack_sender.send(()).unwrap();
+                let _ = ack_sender.send(Ok(()));
             }
+            return Ok(true);
         }
+        Ok(false)
     }
-}
-
-impl Future for Endpoint {
-    type Item = ();
-    type Error = io::Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        loop {
-            match self.io.poll().unwrap() {
+    fn poll_io(&mut self) -> Poll<(), io::Error> {
+        // Nothing more is read once every handle has been dropped.
+        while !self.notifications_closed || !self.requests_closed {
+            match self.io.poll()? {
                 Async::Ready(Some(msg)) => self.handle_msg(msg),
                 Async::Ready(None) => {
+                    debug!("Client: connection closed by the server");
+                    self.fail_pending(RequestError::ConnectionClosed);
                     return Ok(Async::Ready(()));
                 }
                 Async::NotReady => break,
             }
         }
-        if self.shutdown {
-            if self.pending_requests.is_empty() {
-                Ok(Async::Ready(()))
-            } else {
-                Ok(Async::NotReady)
+        let mut flushed;
+        loop {
+            // Each queue is read until its own end, as requests may still be queued once the
+            // notifications have ended, and the other way around.
+            self.process_notifications()?;
+            self.process_requests()?;
+            self.process_responses()?;
+            self.process_pushed()?;
+            self.remove_stale_requests()?;
+            let blocked = !self.unsent.is_empty();
+            // Requests queued before the clients were dropped may not have been written yet.
+            flushed = self.flush()?;
+            // The queues are only read while the transport accepts messages.
+            if !blocked || !self.unsent.is_empty() {
+                break;
             }
+        }
+        // A peer answers the requests it has already received, and the messages still being
+        // written are not dropped with the transport.
+        let closed = self.notifications_closed && self.requests_closed;
+        let idle = self.dispatcher.as_ref().map_or(true, |dispatcher| dispatcher.is_empty());
+        if closed && flushed && self.pending_requests.is_empty() && idle {
+            try_ready!(self.io.close());
+            Ok(Async::Ready(()))
         } else {
-            self.process_notifications();
-            self.process_requests();
-            self.flush();
             Ok(Async::NotReady)
         }
     }
 }
 
+impl<T, C> Future for Endpoint<T, C>
+    where T: AsyncRead + AsyncWrite,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error>
+{
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.poll_io().map_err(|e| {
+            self.fail_pending(RequestError::Io(io::Error::new(e.kind(), e.to_string())));
+            e
+        });
+        match result {
+            Ok(Async::NotReady) => {}
+            _ => {
+                if let Some(closed_tx) = self.closed_tx.take() {
+                    let _ = closed_tx.send(());
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<T, C> Drop for Endpoint<T, C> {
+    fn drop(&mut self) {
+        if let Some(ref mut dispatcher) = self.dispatcher {
+            dispatcher.disconnected();
+        }
+    }
+}
+
 /// A future that returns a `Endpoint` when it completes successfully.
 pub struct Connection {
     client_chan_cancelled: bool,
@@ -299,7 +1235,7 @@ impl Future for Connection {
         } else if let Some(e) = self.poll_error() {
             Err(e)
         } else if self.client_chan_cancelled && self.error_chan_cancelled {
-            panic!("Failed to receive outcome of the connection");
+            Err(io::Error::new(io::ErrorKind::Other, "The connection task was dropped"))
         } else {
             Ok(Async::NotReady)
         }
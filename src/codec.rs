@@ -1,25 +1,233 @@
-use bytes::BytesMut;
-use framed_msgpack::Codec as FramedMsgpack;
+use bytes::{BigEndian, BufMut, ByteOrder, BytesMut, LittleEndian};
+use error::Error;
 use message::Message;
+use rmpv;
 use std::io;
 use tokio_io::codec::{Decoder, Encoder};
 
+/// The maximum number of bytes in a LEB128-encoded 64-bit unsigned integer.
+const MAX_VARINT_LEN: usize = 10;
+
+/// The byte order of a fixed-width length prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+/// The format of the length prefixed to each frame.
+///
+/// The default is a 32-bit unsigned integer encoded in four (4) Big-Endian bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// A 16-bit unsigned integer encoded in two (2) bytes.
+    U16(Endianness),
+    /// A 32-bit unsigned integer encoded in four (4) bytes.
+    U32(Endianness),
+    /// A 64-bit unsigned integer encoded in eight (8) bytes.
+    U64(Endianness),
+    /// An unsigned integer encoded as a [LEB128](https://en.wikipedia.org/wiki/LEB128) varint
+    /// of one (1) to ten (10) bytes.
+    Varint,
+}
+
+impl LengthPrefix {
+    /// Reads the length prefix from the start of `src`.
+    ///
+    /// Returns the length and the number of bytes used by the prefix, or `None` if `src` does not
+    /// contain the complete prefix yet.
+    fn read(&self, src: &[u8]) -> io::Result<Option<(u64, usize)>> {
+        let width = match *self {
+            LengthPrefix::U16(_) => 2,
+            LengthPrefix::U32(_) => 4,
+            LengthPrefix::U64(_) => 8,
+            LengthPrefix::Varint => return read_varint(src),
+        };
+        if src.len() < width {
+            return Ok(None);
+        }
+        let len = match *self {
+            LengthPrefix::U16(Endianness::Big) => BigEndian::read_u16(src) as u64,
+            LengthPrefix::U16(Endianness::Little) => LittleEndian::read_u16(src) as u64,
+            LengthPrefix::U32(Endianness::Big) => BigEndian::read_u32(src) as u64,
+            LengthPrefix::U32(Endianness::Little) => LittleEndian::read_u32(src) as u64,
+            LengthPrefix::U64(Endianness::Big) => BigEndian::read_u64(src),
+            LengthPrefix::U64(Endianness::Little) => LittleEndian::read_u64(src),
+            LengthPrefix::Varint => unreachable!(),
+        };
+        Ok(Some((len, width)))
+    }
+
+    /// Writes `len` as a length prefix to `dst`.
+    fn write(&self, len: u64, dst: &mut BytesMut) -> io::Result<()> {
+        let max = match *self {
+            LengthPrefix::U16(_) => u16::max_value() as u64,
+            LengthPrefix::U32(_) => u32::max_value() as u64,
+            LengthPrefix::U64(_) | LengthPrefix::Varint => u64::max_value(),
+        };
+        if len > max {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The frame length does not fit in the length prefix"));
+        }
+        dst.reserve(self.encoded_len(len));
+        match *self {
+            LengthPrefix::U16(Endianness::Big) => dst.put_u16::<BigEndian>(len as u16),
+            LengthPrefix::U16(Endianness::Little) => dst.put_u16::<LittleEndian>(len as u16),
+            LengthPrefix::U32(Endianness::Big) => dst.put_u32::<BigEndian>(len as u32),
+            LengthPrefix::U32(Endianness::Little) => dst.put_u32::<LittleEndian>(len as u32),
+            LengthPrefix::U64(Endianness::Big) => dst.put_u64::<BigEndian>(len),
+            LengthPrefix::U64(Endianness::Little) => dst.put_u64::<LittleEndian>(len),
+            LengthPrefix::Varint => {
+                let mut len = len;
+                while len >= 0x80 {
+                    dst.put_u8((len as u8 & 0x7f) | 0x80);
+                    len >>= 7;
+                }
+                dst.put_u8(len as u8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the number of bytes used to encode `len` as a length prefix.
+    fn encoded_len(&self, len: u64) -> usize {
+        match *self {
+            LengthPrefix::U16(_) => 2,
+            LengthPrefix::U32(_) => 4,
+            LengthPrefix::U64(_) => 8,
+            LengthPrefix::Varint => {
+                let mut n = 1;
+                let mut len = len >> 7;
+                while len != 0 {
+                    n += 1;
+                    len >>= 7;
+                }
+                n
+            }
+        }
+    }
+}
+
+impl Default for LengthPrefix {
+    fn default() -> Self {
+        LengthPrefix::U32(Endianness::Big)
+    }
+}
+
+fn read_varint(src: &[u8]) -> io::Result<Option<(u64, usize)>> {
+    let mut len: u64 = 0;
+    for (i, byte) in src.iter().take(MAX_VARINT_LEN).enumerate() {
+        let bits = (byte & 0x7f) as u64;
+        if i == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The varint length prefix overflows a 64-bit unsigned integer"));
+        }
+        len |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((len, i + 1)));
+        }
+    }
+    if src.len() >= MAX_VARINT_LEN {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "The varint length prefix is too long"))
+    } else {
+        Ok(None)
+    }
+}
+
+fn message_from_value(value: rmpv::Value, lenient_ids: bool) -> Result<Message, Error> {
+    if lenient_ids {
+        Message::from_value_lenient(value)
+    } else {
+        Message::from_value(value)
+    }
+}
+
+/// Frames MessagePack-RPC messages with a length prefix.
 #[derive(Debug, Clone, Copy)]
 pub struct Codec {
-    inner: FramedMsgpack,
+    length_prefix: LengthPrefix,
+    lenient_ids: bool,
+    max_frame_len: Option<usize>,
+    prefix_counts_itself: bool,
 }
 
 impl Codec {
     /// Creates a new `Codec`.
+    ///
+    /// The length of each frame is prefixed as a 32-bit unsigned integer encoded in four (4)
+    /// Big-Endian bytes.
     pub fn new() -> Self {
-        Codec {
-            inner: FramedMsgpack::new()
+        CodecBuilder::new().build()
+    }
+
+    /// Creates a `CodecBuilder` to configure a `Codec`.
+    pub fn builder() -> CodecBuilder {
+        CodecBuilder::new()
+    }
+
+    /// Gets the format of the length prefix.
+    pub fn length_prefix(&self) -> LengthPrefix {
+        self.length_prefix
+    }
+
+    /// Indicates if message IDs outside the 32-bit range of the specifications are accepted.
+    pub fn lenient_ids(&self) -> bool {
+        self.lenient_ids
+    }
+
+    /// Gets the maximum frame length, in bytes, accepted by this `Codec`.
+    ///
+    /// `None` indicates there is no limit.
+    pub fn max_frame_len(&self) -> Option<usize> {
+        self.max_frame_len
+    }
+
+    /// Indicates if the length prefix includes the bytes of the prefix itself.
+    pub fn prefix_counts_itself(&self) -> bool {
+        self.prefix_counts_itself
+    }
+
+    /// Reads the prefix of the next frame in `src`.
+    ///
+    /// Returns the length of the frame's payload and the number of bytes used by the prefix, or
+    /// `None` if `src` does not contain the complete prefix yet.
+    fn read_prefix(&self, src: &BytesMut) -> io::Result<Option<(u64, usize)>> {
+        let (len, prefix_len) = match self.length_prefix.read(src)? {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+        let len = if self.prefix_counts_itself {
+            if len < prefix_len as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The frame length is less than the length of its prefix"));
+            }
+            len - prefix_len as u64
+        } else {
+            len
+        };
+        if let Some(max) = self.max_frame_len {
+            if len > max as u64 {
+                return Err(Error::FrameTooLarge {
+                    len: len,
+                    max: max,
+                }.into());
+            }
         }
+        Ok(Some((len, prefix_len)))
     }
-    
-    /// Consumes the `Codec`, returning its underlying framed-msgpack codec.
-    pub fn into_inner(self) -> FramedMsgpack {
-        self.inner
+
+    /// Gets the value written to the length prefix for a payload of `len` bytes.
+    fn prefix_value(&self, len: u64) -> u64 {
+        if !self.prefix_counts_itself {
+            return len;
+        }
+        // The width of a varint prefix depends on the value it encodes, so grow the total until
+        // it accounts for its own prefix.
+        let mut total = len;
+        loop {
+            let next = len + self.length_prefix.encoded_len(total) as u64;
+            if next == total {
+                return total;
+            }
+            total = next;
+        }
     }
 }
 
@@ -28,10 +236,18 @@ impl Decoder for Codec {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        match self.inner.decode(src)? {
-            Some(v) => Ok(Some(Message::from_value(v)?)),
-            None => Ok(None)
+        let (len, prefix_len) = match self.read_prefix(src)? {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+        if ((src.len() - prefix_len) as u64) < len {
+            return Ok(None);
         }
+        src.split_to(prefix_len);
+        let payload = src.split_to(len as usize);
+        let value = rmpv::decode::read_value(&mut &payload[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(message_from_value(value, self.lenient_ids)?))
     }
 }
 
@@ -40,15 +256,279 @@ impl Encoder for Codec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: Self::Item, buf: &mut BytesMut) -> io::Result<()> {
-        self.inner.encode(msg.to_value(), buf)
+        let mut payload = Vec::new();
+        rmpv::encode::write_value(&mut payload, &msg.to_value())?;
+        let prefix = self.prefix_value(payload.len() as u64);
+        self.length_prefix.write(prefix, buf)?;
+        buf.extend_from_slice(&payload);
+        Ok(())
     }
 }
 
 impl Default for Codec {
     fn default() -> Self {
+        Codec::new()
+    }
+}
+
+/// A builder for configuring a `Codec`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodecBuilder {
+    length_prefix: LengthPrefix,
+    lenient_ids: bool,
+    max_frame_len: Option<usize>,
+    prefix_counts_itself: bool,
+}
+
+impl CodecBuilder {
+    /// Creates a new `CodecBuilder` with the default configuration.
+    pub fn new() -> Self {
+        CodecBuilder {
+            length_prefix: LengthPrefix::default(),
+            lenient_ids: false,
+            max_frame_len: None,
+            prefix_counts_itself: false,
+        }
+    }
+
+    /// Sets the format of the length prefix.
+    ///
+    /// The default is a 32-bit unsigned integer encoded in four (4) Big-Endian bytes.
+    pub fn length_prefix(mut self, length_prefix: LengthPrefix) -> Self {
+        self.length_prefix = length_prefix;
+        self
+    }
+
+    /// Sets whether message IDs outside the 32-bit range of the specifications are accepted.
+    ///
+    /// By default, a message with an ID that does not fit in a 32-bit unsigned integer is
+    /// rejected with an `Error::IdOutOfRange` error. This should only be enabled for peers known
+    /// to send 64-bit IDs.
+    pub fn lenient_ids(mut self, lenient: bool) -> Self {
+        self.lenient_ids = lenient;
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of a frame's payload.
+    ///
+    /// A frame with a length prefix greater than the maximum is rejected with an
+    /// `Error::FrameTooLarge` error before any of its payload is buffered. By default, there is
+    /// no limit.
+    pub fn max_frame_len(mut self, len: usize) -> Self {
+        self.max_frame_len = Some(len);
+        self
+    }
+
+    /// Sets whether the length prefix includes the bytes of the prefix itself.
+    ///
+    /// By default, the length prefix only counts the bytes of the payload.
+    pub fn prefix_counts_itself(mut self, counts_itself: bool) -> Self {
+        self.prefix_counts_itself = counts_itself;
+        self
+    }
+
+    /// Creates the configured `Codec`.
+    pub fn build(self) -> Codec {
         Codec {
-            inner: FramedMsgpack::new(),
+            length_prefix: self.length_prefix,
+            lenient_ids: self.lenient_ids,
+            max_frame_len: self.max_frame_len,
+            prefix_counts_itself: self.prefix_counts_itself,
+        }
+    }
+}
+
+/// Reads the big-endian unsigned integer of `width` bytes that follows the marker at the start of
+/// `src`, or `None` if `src` does not contain it yet.
+fn read_size(src: &[u8], width: usize) -> Option<u64> {
+    if src.len() < 1 + width {
+        return None;
+    }
+    let bytes = &src[1..1 + width];
+    Some(match width {
+        1 => bytes[0] as u64,
+        2 => BigEndian::read_u16(bytes) as u64,
+        _ => BigEndian::read_u32(bytes) as u64,
+    })
+}
+
+/// Reads the MessagePack marker at the start of `src`.
+///
+/// Returns the length of the header, the length of the data that follows it, and the number of
+/// values contained in an array or map, or `None` if `src` does not contain the complete header
+/// yet.
+fn read_marker(src: &[u8]) -> io::Result<Option<(u64, u64, u64)>> {
+    let marker = match src.first() {
+        Some(marker) => *marker,
+        None => return Ok(None),
+    };
+    // The header, the width of the size that follows the marker, and if the size counts the
+    // bytes of the data (0), the values of an array (1), or the keys and values of a map (2).
+    let (header, width, kind) = match marker {
+        0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => (1, 0, 0),
+        0x80..=0x8f => return Ok(Some((1, 0, 2 * (marker & 0x0f) as u64))),
+        0x90..=0x9f => return Ok(Some((1, 0, (marker & 0x0f) as u64))),
+        0xa0..=0xbf => return Ok(Some((1, (marker & 0x1f) as u64, 0))),
+        0xc4 | 0xd9 => (2, 1, 0),
+        0xc5 | 0xda => (3, 2, 0),
+        0xc6 | 0xdb => (5, 4, 0),
+        0xc7 => (3, 1, 0),
+        0xc8 => (4, 2, 0),
+        0xc9 => (6, 4, 0),
+        0xca => (5, 0, 0),
+        0xcb => (9, 0, 0),
+        0xcc | 0xd0 => (2, 0, 0),
+        0xcd | 0xd1 => (3, 0, 0),
+        0xce | 0xd2 => (5, 0, 0),
+        0xcf | 0xd3 => (9, 0, 0),
+        0xd4 => (3, 0, 0),
+        0xd5 => (4, 0, 0),
+        0xd6 => (6, 0, 0),
+        0xd7 => (10, 0, 0),
+        0xd8 => (18, 0, 0),
+        0xdc => (3, 2, 1),
+        0xdd => (5, 4, 1),
+        0xde => (3, 2, 2),
+        0xdf => (5, 4, 2),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid MessagePack marker")),
+    };
+    if width == 0 {
+        return Ok(Some((header, 0, 0)));
+    }
+    let size = match read_size(src, width) {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    Ok(Some(match kind {
+        0 => (header, size, 0),
+        _ => (header, 0, kind * size),
+    }))
+}
+
+/// The progress of finding the end of the next MessagePack value in a buffer.
+///
+/// Scanning resumes where it stopped when more bytes are received, so a value received over many
+/// reads is only scanned once.
+#[derive(Debug, Clone, Default)]
+struct Scan {
+    /// The number of bytes of the value that have been scanned.
+    len: usize,
+    /// The number of values left to scan in each of the enclosing arrays and maps.
+    remaining: Vec<u64>,
+}
+
+impl Scan {
+    /// Scans `src` from where the previous call stopped.
+    ///
+    /// Returns the length of the value, or `None` if `src` does not contain the complete value
+    /// yet.
+    fn advance(&mut self, src: &[u8], max_frame_len: Option<usize>) -> io::Result<Option<usize>> {
+        if self.remaining.is_empty() {
+            self.remaining.push(1);
+        }
+        loop {
+            while self.remaining.last() == Some(&0) {
+                self.remaining.pop();
+            }
+            if self.remaining.is_empty() {
+                let len = self.len;
+                self.len = 0;
+                return Ok(Some(len));
+            }
+            let (header, data, values) = match read_marker(&src[self.len..])? {
+                Some(marker) => marker,
+                None => return Ok(None),
+            };
+            // Every value takes at least one byte, so an array or map that cannot fit is rejected
+            // before its values are received.
+            let min_len = self.len as u64 + header + data + values;
+            if let Some(max) = max_frame_len {
+                if min_len > max as u64 {
+                    return Err(Error::FrameTooLarge {
+                        len: min_len,
+                        max: max,
+                    }.into());
+                }
+            }
+            if (src.len() as u64) < self.len as u64 + header + data {
+                return Ok(None);
+            }
+            self.len += (header + data) as usize;
+            if let Some(count) = self.remaining.last_mut() {
+                *count -= 1;
+            }
+            if values > 0 {
+                self.remaining.push(values);
+            }
         }
     }
 }
 
+/// Streams MessagePack-RPC messages as bare MessagePack values without a length prefix.
+///
+/// This is the standard transport described in the MessagePack-RPC
+/// [specifications](https://github.com/msgpack-rpc/msgpack-rpc/blob/master/spec.md) and is used by
+/// most implementations, such as msgpack-rpc-python, Neovim, and the Fluentd forward protocol.
+#[derive(Debug, Clone, Default)]
+pub struct UnframedCodec {
+    lenient_ids: bool,
+    max_frame_len: Option<usize>,
+    scan: Scan,
+}
+
+impl UnframedCodec {
+    /// Creates a new `UnframedCodec`.
+    pub fn new() -> Self {
+        UnframedCodec {
+            lenient_ids: false,
+            max_frame_len: None,
+            scan: Scan::default(),
+        }
+    }
+
+    /// Sets whether message IDs outside the 32-bit range of the specifications are accepted.
+    ///
+    /// See `CodecBuilder::lenient_ids`.
+    pub fn lenient_ids(mut self, lenient: bool) -> Self {
+        self.lenient_ids = lenient;
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of a message.
+    ///
+    /// A message is rejected with an `Error::FrameTooLarge` error as soon as the headers received
+    /// show that it is longer than the maximum, before the rest of it is buffered. By default,
+    /// there is no limit. See `CodecBuilder::max_frame_len`.
+    pub fn max_frame_len(mut self, len: usize) -> Self {
+        self.max_frame_len = Some(len);
+        self
+    }
+}
+
+impl Decoder for UnframedCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let len = match self.scan.advance(src, self.max_frame_len)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let payload = src.split_to(len);
+        let value = rmpv::decode::read_value(&mut &payload[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(message_from_value(value, self.lenient_ids)?))
+    }
+}
+
+impl Encoder for UnframedCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Self::Item, buf: &mut BytesMut) -> io::Result<()> {
+        let mut payload = Vec::new();
+        rmpv::encode::write_value(&mut payload, &msg.to_value())?;
+        buf.extend_from_slice(&payload);
+        Ok(())
+    }
+}
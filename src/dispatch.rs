@@ -0,0 +1,254 @@
+//! Dispatching of the requests and notifications received on a connection to a `Handler`.
+
+use futures::{Async, BoxFuture, Future, Poll, Stream};
+use futures::stream::FuturesUnordered;
+use futures::sync::mpsc;
+use message::{Notification, Request, Response, RpcError};
+use rmpv::Value;
+use semaphore::Permit;
+use server::{ErrorFormatter, Handler};
+use std::error::Error;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Formats the error of a failed request handler as an internal `RpcError`.
+pub fn format_error(error: &Error) -> Value {
+    RpcError::internal(error.to_string()).into()
+}
+
+/// A handle to push notifications to the other end of a connection served by a `Server` or a
+/// `Peer`.
+///
+/// The handle can be cloned and sent to other threads. The notifications are queued until they
+/// are written to the connection.
+#[derive(Clone)]
+pub struct Notifier {
+    queued: Arc<AtomicUsize>,
+    tx: mpsc::UnboundedSender<Notification>,
+}
+
+impl Notifier {
+    /// Sends a notification to the other end of the connection.
+    ///
+    /// Fails with `NotConnected` once the connection is no longer served.
+    pub fn notify(&self, method: &str, params: &[Value]) -> io::Result<()> {
+        trace!("Notifier: push notification (method = {}, params = {:?})", method, params);
+        let notification = Notification {
+            method: method.to_owned(),
+            params: Vec::from(params),
+        };
+        // Counted before it is sent so that the connection never takes it off the count first.
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        mpsc::UnboundedSender::send(&self.tx, notification).map_err(|_| {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            io::Error::new(io::ErrorKind::NotConnected, "The connection is no longer served")
+        })
+    }
+
+    /// Returns the number of notifications waiting to be written to the connection, which grows
+    /// when the other end does not read them as fast as they are pushed.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+}
+
+/// The notifications pushed with a `Notifier`, in the order they were pushed.
+pub struct Pushed {
+    queued: Arc<AtomicUsize>,
+    rx: mpsc::UnboundedReceiver<Notification>,
+}
+
+impl Stream for Pushed {
+    type Item = Notification;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let notification = try_ready!(self.rx.poll());
+        if notification.is_some() {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+        Ok(Async::Ready(notification))
+    }
+}
+
+/// Creates a `Notifier`, and the stream of the notifications pushed with it.
+pub fn notifier() -> (Notifier, Pushed) {
+    let queued = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::unbounded();
+    let notifier = Notifier {
+        queued: queued.clone(),
+        tx: tx,
+    };
+    (notifier, Pushed {
+        queued: queued,
+        rx: rx,
+    })
+}
+
+/// Handles incoming requests and notifications, independently of the type of the handler.
+///
+/// This lets a client endpoint serve requests from its peer without being generic over the
+/// handler.
+pub trait Dispatch {
+    /// Passes a request to the handler.
+    fn handle_request(&mut self, request: Request);
+
+    /// Passes a notification to the handler.
+    fn handle_notification(&mut self, notification: Notification);
+
+    /// Polls the notifications being handled, dropping the ones that have completed.
+    fn poll_notifications(&mut self);
+
+    /// Polls the requests being handled, returning the response to one that has completed.
+    fn poll_response(&mut self) -> Option<Response>;
+
+    /// Indicates if no request or notification is being handled.
+    fn is_empty(&self) -> bool;
+
+    /// Tells the handler that the connection is no longer served.
+    fn disconnected(&mut self);
+}
+
+/// The response of a handler to a request, tagged with the id of the request.
+///
+/// The permit of a global concurrency limit, if any, is released when the handler completes.
+struct RequestTask<F> {
+    id: u64,
+    permit: Option<Permit>,
+    response: F,
+}
+
+impl<F: Future> Future for RequestTask<F> {
+    type Item = (u64, Result<F::Item, F::Error>);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.response.poll() {
+            Ok(Async::Ready(response)) => Ok(response),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => Err(e),
+        };
+        self.permit = None;
+        Ok(Async::Ready((self.id, result)))
+    }
+}
+
+/// The requests and notifications received on a connection that are being handled.
+///
+/// Only the requests and notifications whose handlers have notified the task are polled, so the
+/// cost of a wake-up does not grow with the number of pending requests.
+pub struct Dispatcher<H: Handler> {
+    formatter: Arc<ErrorFormatter>,
+    handler: H,
+    request_tasks: FuturesUnordered<RequestTask<BoxFuture<Result<H::T, H::E>, H::Error>>>,
+    notification_tasks: FuturesUnordered<BoxFuture<(), H::Error>>,
+}
+
+impl<H: Handler> Dispatcher<H> {
+    /// Creates a new `Dispatcher`.
+    pub fn new(handler: H) -> Self {
+        Dispatcher {
+            formatter: Arc::new(format_error),
+            handler: handler,
+            request_tasks: FuturesUnordered::new(),
+            notification_tasks: FuturesUnordered::new(),
+        }
+    }
+
+    /// Gets the handler of the requests and notifications.
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Sets the function that formats the error of a failed request handler as the error of its
+    /// response.
+    pub fn set_error_formatter(&mut self, formatter: Arc<ErrorFormatter>) {
+        self.formatter = formatter;
+    }
+
+    /// Passes a request to the handler, holding the `permit` until the handler completes.
+    pub fn handle_request_with_permit(&mut self, request: Request, permit: Option<Permit>) {
+        let method = request.method.as_str();
+        let params = request.params;
+        trace!("Dispatcher: request (method = {}, params = {:?})", method, params);
+        let response = self.handler.handle_request(method, &params);
+        self.request_tasks.push(RequestTask {
+            id: request.id,
+            permit: permit,
+            response: response,
+        });
+    }
+
+    /// The number of requests being handled.
+    pub fn pending_requests(&self) -> usize {
+        self.request_tasks.len()
+    }
+
+    /// The number of notifications being handled.
+    pub fn pending_notifications(&self) -> usize {
+        self.notification_tasks.len()
+    }
+
+    /// Indicates if no request or notification is being handled.
+    pub fn is_empty(&self) -> bool {
+        self.request_tasks.is_empty() && self.notification_tasks.is_empty()
+    }
+
+    /// Drops the requests and notifications being handled, without responding to the requests.
+    pub fn clear(&mut self) {
+        self.request_tasks = FuturesUnordered::new();
+        self.notification_tasks = FuturesUnordered::new();
+    }
+}
+
+impl<H: Handler> Dispatch for Dispatcher<H> {
+    fn handle_request(&mut self, request: Request) {
+        self.handle_request_with_permit(request, None);
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        let method = notification.method.as_str();
+        let params = notification.params;
+        trace!("Dispatcher: notification (method = {}, params = {:?})", method, params);
+        let outcome = self.handler.handle_notification(method, &params);
+        self.notification_tasks.push(outcome);
+    }
+
+    fn poll_notifications(&mut self) {
+        trace!("Dispatcher: process notifications");
+        loop {
+            match self.notification_tasks.poll() {
+                Ok(Async::Ready(Some(_))) => continue,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => return,
+                Err(e) => warn!("Dispatcher: notification failed ({})", e),
+            }
+        }
+    }
+
+    fn poll_response(&mut self) -> Option<Response> {
+        let (id, result) = match self.request_tasks.poll() {
+            Ok(Async::Ready(Some(done))) => done,
+            Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => return None,
+        };
+        let result = match result {
+            Ok(response) => response.map(|v| v.into()).map_err(|e| e.into()),
+            Err(e) => {
+                error!("Dispatcher: request failed ({})", e);
+                Err((self.formatter)(&e))
+            }
+        };
+        Some(Response {
+            id: id,
+            result: result,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        Dispatcher::is_empty(self)
+    }
+
+    fn disconnected(&mut self) {
+        self.handler.disconnected();
+    }
+}
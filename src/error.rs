@@ -0,0 +1,96 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// The errors that can occur while receiving a `MessagePack-RPC` message.
+///
+/// The codecs report these errors wrapped in an `io::Error` with the `InvalidData` kind, as
+/// required by the tokio codec traits. The original error can be recovered with
+/// `io::Error::get_ref` and `downcast_ref::<Error>`.
+#[derive(Debug)]
+pub enum Error {
+    /// The message is not a MessagePack array.
+    NotAnArray,
+    /// The message array does not have the expected number of elements.
+    WrongArity {
+        expected: usize,
+        got: usize,
+    },
+    /// The message type is not an unsigned integer.
+    BadMessageType,
+    /// The message type is not a request (0), response (1), or notification (2).
+    UnknownMessageType(u64),
+    /// The message ID is missing or not an unsigned integer.
+    BadId,
+    /// The message ID does not fit in a 32-bit unsigned integer.
+    IdOutOfRange(u64),
+    /// The method is missing or not a UTF-8 string.
+    BadMethod,
+    /// The parameters are not an array.
+    BadParams,
+    /// The length prefix announces a frame larger than the maximum frame length.
+    FrameTooLarge {
+        len: u64,
+        max: usize,
+    },
+    /// An I/O error occurred.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WrongArity { expected, got } => {
+                write!(f, "The message has {} array elements, but {} were expected", got, expected)
+            }
+            Error::UnknownMessageType(msg_type) => write!(f, "Unknown message type ({})", msg_type),
+            Error::IdOutOfRange(id) => {
+                write!(f, "The message ID ({}) exceeds the range of a 32-bit unsigned integer", id)
+            }
+            Error::FrameTooLarge { len, max } => {
+                write!(f, "The frame length ({} bytes) exceeds the maximum frame length ({} bytes)", len, max)
+            }
+            Error::Io(ref e) => write!(f, "{}", e),
+            _ => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NotAnArray => "The message must be an array type according to the msgpack-rpc specification",
+            Error::WrongArity { .. } => "The message does not have the expected number of array elements",
+            Error::BadMessageType => "Message type is not an integer",
+            Error::UnknownMessageType(_) => "Unknown message type",
+            Error::BadId => "The message ID is not an integer",
+            Error::IdOutOfRange(_) => "The message ID exceeds the range of a 32-bit unsigned integer",
+            Error::BadMethod => "The method is not a string",
+            Error::BadParams => "The parameters are not an array",
+            Error::FrameTooLarge { .. } => "The frame length exceeds the maximum frame length",
+            Error::Io(ref e) => error::Error::description(e),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
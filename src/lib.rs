@@ -1,6 +1,10 @@
 //! Frame a MessagePack-RPC message (payload) from a transport with the total message length prefixed as
 //! a 32-bit unsigned integer encoded in four (4) bytes.
 //!
+//! Other length prefix formats, such as 16-bit, Little-Endian, or LEB128 varint prefixes, can be
+//! selected with the `CodecBuilder`. The `UnframedCodec` streams bare MessagePack values without a
+//! length prefix, as most other MessagePack-RPC implementations do.
+//!
 //! # Getting Started
 //!
 //! This can be used with the tokio-proto crate.
@@ -26,18 +30,30 @@
 //! ```
 
 extern crate bytes;
-extern crate framed_msgpack;
+#[macro_use]
 extern crate futures;
 #[macro_use]
 extern crate log;
+extern crate rand;
 extern crate rmpv;
+extern crate serde;
 extern crate tokio_core;
 extern crate tokio_io;
+#[cfg(unix)]
+extern crate tokio_uds;
 
-pub use self::codec::Codec;
+pub use self::codec::{Codec, CodecBuilder, Endianness, LengthPrefix, UnframedCodec};
+pub use self::error::Error;
 
+pub mod broker;
 pub mod client;
 mod codec;
+mod dispatch;
+mod error;
 pub mod message;
+pub mod pool;
+pub mod reconnect;
+pub mod router;
+mod semaphore;
 pub mod server;
 
@@ -1,8 +1,12 @@
 // Portions of this were taken from the [rmp-rpc](https://github.com/little-dude/rmp-rpc) project.
 
-use std::io;
+use error::Error;
 use rmpv::{Integer, Utf8String, Value};
-use std::convert::From;
+use rmpv::ext;
+use serde::de::DeserializeOwned;
+use std::convert::{From, TryFrom};
+use std::error;
+use std::fmt;
 
 /// Represents a `MessagePack-RPC` message as described in the
 /// [specifications](https://github.com/msgpack-rpc/msgpack-rpc/blob/master/spec.md#messagepack-rpc-protocol-specification)
@@ -17,7 +21,11 @@ pub enum Message {
 /// [specifications](https://github.com/msgpack-rpc/msgpack-rpc/blob/master/spec.md#messagepack-rpc-protocol-specification)
 #[derive(PartialEq, Clone, Debug)]
 pub struct Request {
-    pub id: u32,
+    /// The message ID.
+    ///
+    /// The specifications limit IDs to 32-bit unsigned integers, but IDs are stored as 64-bit
+    /// unsigned integers to support peers that send larger IDs, see `Message::from_value_lenient`.
+    pub id: u64,
     pub method: String,
     pub params: Vec<Value>,
 }
@@ -26,7 +34,8 @@ pub struct Request {
 /// [specifications](https://github.com/msgpack-rpc/msgpack-rpc/blob/master/spec.md#messagepack-rpc-protocol-specification)
 #[derive(PartialEq, Clone, Debug)]
 pub struct Response {
-    pub id: u32,
+    /// The ID of the request this is a response to.
+    pub id: u64,
     pub result: Result<Value, Value>,
 }
 
@@ -38,40 +47,70 @@ pub struct Notification {
     pub params: Vec<Value>,
 }
 
+/// A standard error of a `MessagePack-RPC` response.
+///
+/// The error is sent as a map with a `code`, a `message`, and optionally some `data`. The codes
+/// from -32768 to -32000 are reserved for the predefined errors, the other codes can be used by
+/// applications.
+#[derive(PartialEq, Clone, Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
 const REQUEST_MESSAGE: u64 = 0;
 const RESPONSE_MESSAGE: u64 = 1;
 const NOTIFICATION_MESSAGE: u64 = 2;
+const MAX_ID: u64 = 0xffff_ffff;
 
 impl Message {
     /// Converts a MessagePack value to a MessagePack-RPC message.
     ///
     /// This conversion can fail if the MessagePack value does not match the msgpack-rpc
+    /// specification. A message ID that does not fit in a 32-bit unsigned integer is rejected
+    /// with `Error::IdOutOfRange`.
+    pub fn from_value(v: Value) -> Result<Message, Error> {
+        Message::parse(v, false)
+    }
+
+    /// Converts a MessagePack value to a MessagePack-RPC message, accepting any 64-bit unsigned
+    /// integer as a message ID.
+    ///
+    /// This is intended for peers known to send IDs outside the 32-bit range of the
     /// specification.
-    pub fn from_value(v: Value) -> io::Result<Message> {
+    pub fn from_value_lenient(v: Value) -> Result<Message, Error> {
+        Message::parse(v, true)
+    }
+
+    fn parse(v: Value, lenient_ids: bool) -> Result<Message, Error> {
         if let Value::Array(ref array) = v {
             if array.len() < 3 {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "The message does not have at least three array elements"))
+                return Err(Error::WrongArity { expected: 3, got: array.len() });
             }
             if let Value::Integer(msg_type) = array[0] {
                 match msg_type.as_u64() {
                     Some(REQUEST_MESSAGE) => {
-                        return Ok(Message::Request(Request::from_value(array)?));
+                        return Ok(Message::Request(Request::from_value(array, lenient_ids)?));
                     }
                     Some(RESPONSE_MESSAGE) => {
-                        return Ok(Message::Response(Response::from_value(array)?));
+                        return Ok(Message::Response(Response::from_value(array, lenient_ids)?));
                     }
                     Some(NOTIFICATION_MESSAGE) => {
                         return Ok(Message::Notification(Notification::from_value(array)?));
                     }
-                    _ => {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown message type"))
+                    Some(msg_type) => {
+                        return Err(Error::UnknownMessageType(msg_type))
+                    }
+                    None => {
+                        return Err(Error::BadMessageType)
                     }
                 }
             } else {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "Message type is not an integer")) 
+                return Err(Error::BadMessageType)
             }
         } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The message must be an array type according to the msgpack-rpc specification"))
+            return Err(Error::NotAnArray)
         }
     }
 
@@ -86,22 +125,30 @@ impl Message {
 }
 
 impl Notification {
-    fn from_value(array: &[Value]) -> io::Result<Self> {
+    /// Deserializes the parameters into a tuple, sequence, or struct.
+    ///
+    /// The parameters are deserialized as an array, so a single parameter must be deserialized
+    /// into a one-element tuple, e.g. `(String,)`.
+    pub fn deserialize_params<T: DeserializeOwned>(&self) -> Result<T, ext::Error> {
+        ext::from_value(Value::Array(self.params.clone()))
+    }
+
+    fn from_value(array: &[Value]) -> Result<Self, Error> {
         if array.len() != 3 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The notification does not have three array elements"))
+            return Err(Error::WrongArity { expected: 3, got: array.len() });
         }
         let method = if let Value::String(ref method) = array[1] {
             method
                 .as_str()
                 .and_then(|s| Some(s.to_string()))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "The notification method is not a string"))?
+                .ok_or(Error::BadMethod)?
         } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The notification does not have a method"));
+            return Err(Error::BadMethod);
         };
         let params = if let Value::Array(ref params) = array[2] {
             params.clone()
         } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The notification does not have any parameters"));
+            return Err(Error::BadParams);
         };
         Ok(Notification {
             method: method,
@@ -121,29 +168,31 @@ impl Notification {
 }
 
 impl Request {
-    fn from_value(array: &[Value]) -> io::Result<Self> {
+    /// Deserializes the parameters into a tuple, sequence, or struct.
+    ///
+    /// The parameters are deserialized as an array, so a single parameter must be deserialized
+    /// into a one-element tuple, e.g. `(String,)`.
+    pub fn deserialize_params<T: DeserializeOwned>(&self) -> Result<T, ext::Error> {
+        ext::from_value(Value::Array(self.params.clone()))
+    }
+
+    fn from_value(array: &[Value], lenient_ids: bool) -> Result<Self, Error> {
         if array.len() != 4 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The request does not have four array elements"));
+            return Err(Error::WrongArity { expected: 4, got: array.len() });
         }
-        let id = if let Value::Integer(id) = array[1] {
-            id.as_u64()
-                .and_then(|id| Some(id as u32))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "The request ID is not an integer"))?
-        } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The request does not have an ID"));
-        };
+        let id = id_from_value(&array[1], lenient_ids)?;
         let method = if let Value::String(ref method) = array[2] {
             method
                 .as_str()
                 .and_then(|s| Some(s.to_string()))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "The request method is not a string"))?
+                .ok_or(Error::BadMethod)?
         } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The request does not have a method"));
+            return Err(Error::BadMethod);
         };
         let params = if let Value::Array(ref params) = array[3] {
             params.clone()
         } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The request does not have any parameters"));
+            return Err(Error::BadParams);
         };
         Ok(Request {
             id: id,
@@ -163,17 +212,11 @@ impl Request {
 }
 
 impl Response {
-    fn from_value(array: &[Value]) -> io::Result<Self> {
+    fn from_value(array: &[Value], lenient_ids: bool) -> Result<Self, Error> {
         if array.len() != 4 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The response does not have four array elements"));
+            return Err(Error::WrongArity { expected: 4, got: array.len() });
         }
-        let id = if let Value::Integer(id) = array[1] {
-            id.as_u64()
-                .and_then(|id| Some(id as u32))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "The response ID is not an integer"))?
-        } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "The response does not have an ID"));
-        };
+        let id = id_from_value(&array[1], lenient_ids)?;
         match array[2] {
             Value::Nil => Ok(Response {
                 id: id,
@@ -200,3 +243,117 @@ impl Response {
     }
 }
 
+impl RpcError {
+    /// The method of the request does not exist.
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    /// The parameters of the request are invalid.
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// The handler of the request failed.
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// The request was not handled in time.
+    pub const TIMEOUT: i64 = -32000;
+    /// The server is handling too many requests to handle this one.
+    pub const OVERLOADED: i64 = -32001;
+
+    /// Creates a new `RpcError` without data.
+    pub fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        RpcError {
+            code: code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Adds data to the error.
+    pub fn with_data<V: Into<Value>>(mut self, data: V) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Creates an error for a request to a method that does not exist.
+    pub fn method_not_found(method: &str) -> Self {
+        RpcError::new(RpcError::METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    /// Creates an error for a request with invalid parameters.
+    pub fn invalid_params<S: Into<String>>(message: S) -> Self {
+        RpcError::new(RpcError::INVALID_PARAMS, message)
+    }
+
+    /// Creates an error for a request whose handler failed.
+    pub fn internal<S: Into<String>>(message: S) -> Self {
+        RpcError::new(RpcError::INTERNAL_ERROR, message)
+    }
+
+    /// Creates an error for a request that was not handled in time.
+    pub fn timeout() -> Self {
+        RpcError::new(RpcError::TIMEOUT, "Timed out")
+    }
+
+    /// Creates an error for a request that was rejected because the server is overloaded.
+    pub fn overloaded() -> Self {
+        RpcError::new(RpcError::OVERLOADED, "Overloaded")
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (code = {})", self.message, self.code)
+    }
+}
+
+impl error::Error for RpcError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<RpcError> for Value {
+    fn from(error: RpcError) -> Value {
+        let mut map = vec![(Value::from("code"), Value::from(error.code)),
+                           (Value::from("message"), Value::from(error.message))];
+        if let Some(data) = error.data {
+            map.push((Value::from("data"), data));
+        }
+        Value::Map(map)
+    }
+}
+
+impl TryFrom<Value> for RpcError {
+    type Error = Value;
+
+    /// Converts the error of a response to an `RpcError`, or gives the value back if it does not
+    /// follow the schema.
+    fn try_from(value: Value) -> Result<Self, Value> {
+        let (code, message, data) = {
+            let map = match value.as_map() {
+                Some(map) => map,
+                None => return Err(value),
+            };
+            let field = |name: &str| map.iter().find(|&&(ref k, _)| k.as_str() == Some(name)).map(|&(_, ref v)| v);
+            let code = field("code").and_then(|code| code.as_i64());
+            let message = field("message").and_then(|message| message.as_str());
+            match (code, message) {
+                (Some(code), Some(message)) => (code, message.to_owned(), field("data").cloned()),
+                _ => return Err(value.clone()),
+            }
+        };
+        Ok(RpcError {
+            code: code,
+            message: message,
+            data: data,
+        })
+    }
+}
+
+fn id_from_value(v: &Value, lenient: bool) -> Result<u64, Error> {
+    if let Value::Integer(id) = *v {
+        let id = id.as_u64().ok_or(Error::BadId)?;
+        if !lenient && id > MAX_ID {
+            return Err(Error::IdOutOfRange(id));
+        }
+        Ok(id)
+    } else {
+        Err(Error::BadId)
+    }
+}
@@ -0,0 +1,263 @@
+//! A pool of clients that balances requests and notifications across several replicas of a
+//! server.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let pool = PoolBuilder::new()
+//!     .strategy(Strategy::LeastOutstanding)
+//!     .connect(&[addr1, addr2, addr3], &handle);
+//! pool.request("sayHello", &[Value::from("World")])
+//! ```
+
+use client::RequestError;
+use codec::Codec;
+use futures::{Async, Future, Poll};
+use message::Message;
+use reconnect::{Delivery, ReconnectBuilder, ReconnectingClient, Reply, State};
+use rmpv::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_core::reactor::Handle;
+use tokio_io::codec::{Decoder, Encoder};
+
+/// The number of points of each member on the hash ring.
+const VIRTUAL_NODES: usize = 64;
+
+/// How a `Pool` chooses the member that a request or notification is sent to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Sends to each member in turn.
+    RoundRobin,
+    /// Sends to the member with the fewest requests waiting for their response.
+    LeastOutstanding,
+    /// Sends the requests and notifications with the same key to the same member, as long as it
+    /// is connected. Those without a key are sent to each member in turn.
+    ConsistentHash,
+}
+
+/// A builder for a `Pool`, used to configure the strategy and the clients of its members.
+pub struct PoolBuilder<C = Codec> {
+    client: ReconnectBuilder<C>,
+    strategy: Strategy,
+}
+
+impl PoolBuilder {
+    /// Creates a new `PoolBuilder` with the round-robin strategy.
+    pub fn new() -> Self {
+        PoolBuilder {
+            client: ReconnectBuilder::new(),
+            strategy: Strategy::RoundRobin,
+        }
+    }
+}
+
+impl Default for PoolBuilder {
+    fn default() -> Self {
+        PoolBuilder::new()
+    }
+}
+
+impl<C> PoolBuilder<C>
+    where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    /// Configures the client of each member with the `client` builder, e.g. to set its backoff.
+    pub fn client<C2>(self, client: ReconnectBuilder<C2>) -> PoolBuilder<C2> {
+        PoolBuilder {
+            client: client,
+            strategy: self.strategy,
+        }
+    }
+
+    /// Sets the strategy used to choose a member.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Connects a member of the pool to each address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no addresses.
+    pub fn connect(self, addrs: &[SocketAddr], handle: &Handle) -> Pool {
+        assert!(!addrs.is_empty(), "A pool needs at least one address");
+        let members: Vec<Member> = addrs.iter()
+            .map(|addr| {
+                Member {
+                    client: self.client.clone().connect(addr, handle),
+                    outstanding: Arc::new(AtomicUsize::new(0)),
+                }
+            })
+            .collect();
+        let mut ring = Vec::with_capacity(addrs.len() * VIRTUAL_NODES);
+        for (index, addr) in addrs.iter().enumerate() {
+            for node in 0..VIRTUAL_NODES {
+                ring.push((hash(&(addr, node)), index));
+            }
+        }
+        ring.sort();
+        Pool {
+            members: Arc::new(members),
+            next: Arc::new(AtomicUsize::new(0)),
+            ring: Arc::new(ring),
+            strategy: self.strategy,
+        }
+    }
+}
+
+fn hash<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Member {
+    client: ReconnectingClient,
+    outstanding: Arc<AtomicUsize>,
+}
+
+/// A pool of `ReconnectingClient`s, one for each replica of a server.
+///
+/// The members that are not connected are left out until their client has dialed the server
+/// again. If no member is connected, the request or notification is sent to one of them anyway,
+/// and handled according to its `reconnect::Policy`.
+///
+/// The pool can be cloned to send requests and notifications from several places.
+#[derive(Clone)]
+pub struct Pool {
+    members: Arc<Vec<Member>>,
+    next: Arc<AtomicUsize>,
+    ring: Arc<Vec<(u64, usize)>>,
+    strategy: Strategy,
+}
+
+impl Pool {
+    /// Connects a member of the pool to each address, with the round-robin strategy.
+    ///
+    /// This is a shortcut for `PoolBuilder::new().connect(addrs, handle)`.
+    pub fn connect(addrs: &[SocketAddr], handle: &Handle) -> Pool {
+        PoolBuilder::new().connect(addrs, handle)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request to a member of the pool.
+    pub fn request(&self, method: &str, params: &[Value]) -> Balanced {
+        self.send_request(None, method, params)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request to the member of the pool for the `key`.
+    ///
+    /// The key is only used by the `ConsistentHash` strategy.
+    pub fn request_with_key<K: Hash + ?Sized>(&self, key: &K, method: &str, params: &[Value]) -> Balanced {
+        self.send_request(Some(hash(key)), method, params)
+    }
+
+    fn send_request(&self, key: Option<u64>, method: &str, params: &[Value]) -> Balanced {
+        let member = &self.members[self.select(key)];
+        member.outstanding.fetch_add(1, Ordering::SeqCst);
+        Balanced {
+            inner: member.client.request(method, params),
+            outstanding: Some(Outstanding { count: member.outstanding.clone() }),
+        }
+    }
+
+    /// Send a `Framed-MessagePack-RPC` notification to a member of the pool.
+    pub fn notify(&self, method: &str, params: &[Value]) -> Delivery {
+        self.members[self.select(None)].client.notify(method, params)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` notification to the member of the pool for the `key`.
+    ///
+    /// The key is only used by the `ConsistentHash` strategy.
+    pub fn notify_with_key<K: Hash + ?Sized>(&self, key: &K, method: &str, params: &[Value]) -> Delivery {
+        self.members[self.select(Some(hash(key)))].client.notify(method, params)
+    }
+
+    /// Returns the number of members in the pool.
+    pub fn members(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns the number of members that are connected.
+    pub fn connected(&self) -> usize {
+        self.members.iter().filter(|member| member.client.state() == State::Connected).count()
+    }
+
+    /// Returns the index of the member to send a message to.
+    fn select(&self, key: Option<u64>) -> usize {
+        let connected: Vec<bool> = self.members
+            .iter()
+            .map(|member| member.client.state() == State::Connected)
+            .collect();
+        let any_connected = connected.iter().any(|c| *c);
+        let eligible = |index: usize| !any_connected || connected[index];
+        match (self.strategy, key) {
+            (Strategy::ConsistentHash, Some(key)) => {
+                let start = match self.ring.binary_search(&(key, 0)) {
+                    Ok(position) | Err(position) => position,
+                };
+                let len = self.ring.len();
+                (0..len)
+                    .map(|offset| self.ring[(start + offset) % len].1)
+                    .find(|index| eligible(*index))
+                    .unwrap()
+            }
+            (Strategy::LeastOutstanding, _) => {
+                // The members with the same number of outstanding requests are taken in turn.
+                let start = self.next.fetch_add(1, Ordering::SeqCst);
+                let len = self.members.len();
+                (0..len)
+                    .map(|offset| (start + offset) % len)
+                    .filter(|index| eligible(*index))
+                    .min_by_key(|index| self.members[*index].outstanding.load(Ordering::SeqCst))
+                    .unwrap()
+            }
+            _ => {
+                let len = self.members.len();
+                loop {
+                    let index = self.next.fetch_add(1, Ordering::SeqCst) % len;
+                    if eligible(index) {
+                        return index;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Counts a request as outstanding until it is dropped.
+struct Outstanding {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for Outstanding {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A future that returns the outcome of a request sent with a `Pool`.
+///
+/// The request is outstanding for its member until the future completes or is dropped.
+pub struct Balanced {
+    inner: Reply,
+    outstanding: Option<Outstanding>,
+}
+
+impl Future for Balanced {
+    type Item = Result<Value, Value>;
+    type Error = RequestError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.inner.poll();
+        match result {
+            Ok(Async::NotReady) => {}
+            _ => self.outstanding = None,
+        }
+        result
+    }
+}
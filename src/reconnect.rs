@@ -0,0 +1,561 @@
+//! A client that dials the server again when its connection is lost.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let client = ReconnectBuilder::new()
+//!     .backoff(Duration::from_millis(100), Duration::from_secs(10))
+//!     .policy(Policy::Queue(100))
+//!     .connect(&addr, &handle);
+//! let changes = client.state_changes().for_each(|state| Ok(println!("{:?}", state)));
+//! ```
+
+use client::{Ack, ClientBuilder, Client, Closed, Connection, RequestError, Response};
+use codec::Codec;
+use futures::{Async, Future, Poll, Stream};
+use futures::sync::{mpsc, oneshot};
+use message::Message;
+use rand::{self, Rng};
+use rmpv::Value;
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::codec::{Decoder, Encoder};
+
+type ResponseSender = oneshot::Sender<Result<Result<Value, Value>, RequestError>>;
+type AckSender = oneshot::Sender<Result<(), RequestError>>;
+
+/// The default delay before the first attempt to reconnect.
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 100;
+
+/// The default maximum delay between two attempts to connect.
+const DEFAULT_MAX_BACKOFF_MS: u64 = 30_000;
+
+/// The state of the connection of a `ReconnectingClient`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    /// The client is dialing the server.
+    Connecting,
+    /// The client is connected to the server.
+    Connected,
+    /// The connection was lost, or could not be established, and the client is waiting before
+    /// dialing the server again.
+    Disconnected,
+}
+
+/// What to do with the requests and notifications sent while a `ReconnectingClient` is not
+/// connected.
+///
+/// The requests and notifications that were already written to a connection when it was lost
+/// fail, whatever the policy. They are never sent again implicitly, since the server may have
+/// received them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// Fails them with `RequestError::ConnectionClosed`.
+    Fail,
+    /// Queues up to the number of them until the client is connected again, and fails the others
+    /// with `RequestError::ConnectionClosed`.
+    Queue(usize),
+}
+
+/// The address of the server.
+#[derive(Clone)]
+enum Target {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// A builder for a `ReconnectingClient`, used to configure the backoff and the policy for the
+/// messages sent while disconnected.
+#[derive(Clone)]
+pub struct ReconnectBuilder<C = Codec> {
+    client: ClientBuilder<C>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    policy: Policy,
+}
+
+impl ReconnectBuilder {
+    /// Creates a new `ReconnectBuilder`.
+    ///
+    /// The default backoff starts at 100 milliseconds and is capped at 30 seconds, and the
+    /// requests and notifications sent while disconnected fail.
+    pub fn new() -> Self {
+        ReconnectBuilder {
+            client: ClientBuilder::new(),
+            initial_backoff: Duration::from_millis(DEFAULT_INITIAL_BACKOFF_MS),
+            max_backoff: Duration::from_millis(DEFAULT_MAX_BACKOFF_MS),
+            policy: Policy::Fail,
+        }
+    }
+}
+
+impl Default for ReconnectBuilder {
+    fn default() -> Self {
+        ReconnectBuilder::new()
+    }
+}
+
+impl<C> ReconnectBuilder<C>
+    where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    /// Configures each connection with the `client` builder, e.g. to set a codec or the default
+    /// deadline of requests.
+    pub fn client<C2>(self, client: ClientBuilder<C2>) -> ReconnectBuilder<C2> {
+        ReconnectBuilder {
+            client: client,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            policy: self.policy,
+        }
+    }
+
+    /// Sets the delay before the first attempt to reconnect, and the maximum delay between two
+    /// attempts.
+    ///
+    /// The delay doubles after each failed attempt, and is reset once connected. A random jitter
+    /// of up to half the delay is subtracted, so that clients disconnected at the same time do
+    /// not all dial the server again at the same time.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets the policy for the requests and notifications sent while disconnected.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Connects the client to a remote `Framed-MessagePack-RPC` server.
+    ///
+    /// The client is driven by a task spawned on the event loop of the `handle`, which dials the
+    /// server until all the clones of the client have been dropped.
+    pub fn connect(self, addr: &SocketAddr, handle: &Handle) -> ReconnectingClient {
+        self.spawn(Target::Tcp(*addr), handle)
+    }
+
+    /// Connects the client to a local `Framed-MessagePack-RPC` server listening on a Unix domain
+    /// socket.
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(self, path: P, handle: &Handle) -> ReconnectingClient {
+        self.spawn(Target::Unix(path.as_ref().to_owned()), handle)
+    }
+
+    fn spawn(self, target: Target, handle: &Handle) -> ReconnectingClient {
+        let (commands_tx, commands_rx) = mpsc::unbounded();
+        let shared = Arc::new(Mutex::new(Shared {
+            state: State::Connecting,
+            subscribers: Vec::new(),
+        }));
+        let mut supervisor = Supervisor {
+            attempts: 0,
+            builder: self.client,
+            commands_rx: commands_rx,
+            handle: handle.clone(),
+            initial_backoff: self.initial_backoff,
+            link: Link::Waiting(None),
+            max_backoff: self.max_backoff,
+            policy: self.policy,
+            queue: VecDeque::new(),
+            shared: shared.clone(),
+            target: target,
+        };
+        supervisor.dial();
+        handle.spawn(supervisor);
+        ReconnectingClient {
+            commands_tx: commands_tx,
+            shared: shared,
+        }
+    }
+}
+
+/// A request or notification sent to the task driving a `ReconnectingClient`.
+enum Command {
+    Request(String, Vec<Value>, Option<Duration>, ResponseSender),
+    Notify(String, Vec<Value>, AckSender),
+}
+
+impl Command {
+    /// Indicates if the `Reply` to a request has been dropped.
+    ///
+    /// A notification is never abandoned, as it is sent whether or not its `Delivery` is kept.
+    fn is_abandoned(&self) -> bool {
+        match *self {
+            Command::Request(_, _, _, ref tx) => tx.is_canceled(),
+            Command::Notify(..) => false,
+        }
+    }
+
+    fn fail(self, error: RequestError) {
+        match self {
+            Command::Request(_, _, _, tx) => {
+                let _ = tx.send(Err(error));
+            }
+            Command::Notify(_, _, tx) => {
+                let _ = tx.send(Err(error));
+            }
+        }
+    }
+}
+
+struct Shared {
+    state: State,
+    subscribers: Vec<mpsc::UnboundedSender<State>>,
+}
+
+/// A client that dials the server again, with a jittered exponential backoff, whenever its
+/// connection is lost.
+///
+/// The client can be cloned to send requests and notifications from several places over the
+/// same connection.
+#[derive(Clone)]
+pub struct ReconnectingClient {
+    commands_tx: mpsc::UnboundedSender<Command>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ReconnectingClient {
+    /// Connects the client to a remote `Framed-MessagePack-RPC` server with the default backoff
+    /// and policy.
+    ///
+    /// This is a shortcut for `ReconnectBuilder::new().connect(addr, handle)`.
+    pub fn connect(addr: &SocketAddr, handle: &Handle) -> ReconnectingClient {
+        ReconnectBuilder::new().connect(addr, handle)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request, see `Client::request`.
+    pub fn request(&self, method: &str, params: &[Value]) -> Reply {
+        self.send_request(method, params, None)
+    }
+
+    /// Send a `Framed-MessagePack-RPC` request with a deadline, see
+    /// `Client::request_with_timeout`.
+    ///
+    /// The deadline starts when the request is written to a connection, not while it is queued.
+    pub fn request_with_timeout(&self, method: &str, params: &[Value], timeout: Duration) -> Reply {
+        self.send_request(method, params, Some(timeout))
+    }
+
+    fn send_request(&self, method: &str, params: &[Value], timeout: Option<Duration>) -> Reply {
+        let (tx, rx) = oneshot::channel();
+        let command = Command::Request(method.to_owned(), Vec::from(params), timeout, tx);
+        if let Err(e) = mpsc::UnboundedSender::send(&self.commands_tx, command) {
+            e.into_inner().fail(RequestError::ConnectionClosed);
+        }
+        Reply { inner: rx }
+    }
+
+    /// Send a `Framed-MessagePack-RPC` notification, see `Client::notify`.
+    ///
+    /// A notification queued while the client is disconnected is sent once it reconnects, even if
+    /// the `Delivery` has been dropped.
+    pub fn notify(&self, method: &str, params: &[Value]) -> Delivery {
+        let (tx, rx) = oneshot::channel();
+        let command = Command::Notify(method.to_owned(), Vec::from(params), tx);
+        if let Err(e) = mpsc::UnboundedSender::send(&self.commands_tx, command) {
+            e.into_inner().fail(RequestError::ConnectionClosed);
+        }
+        Delivery { inner: rx }
+    }
+
+    /// Returns the current state of the connection.
+    pub fn state(&self) -> State {
+        self.shared.lock().unwrap().state
+    }
+
+    /// Returns a stream of the changes to the state of the connection.
+    ///
+    /// The stream receives the changes that happen after it was created, and ends when the task
+    /// driving the client stops.
+    pub fn state_changes(&self) -> StateChanges {
+        let (tx, rx) = mpsc::unbounded();
+        self.shared.lock().unwrap().subscribers.push(tx);
+        StateChanges { inner: rx }
+    }
+}
+
+/// A future that returns the outcome of a request sent with a `ReconnectingClient`.
+///
+/// Dropping it abandons the request, as for a `Response`. An abandoned request that is queued
+/// while the client is disconnected is not sent once it reconnects, and does not count towards
+/// the limit of `Policy::Queue`.
+pub struct Reply {
+    inner: oneshot::Receiver<Result<Result<Value, Value>, RequestError>>,
+}
+
+impl Future for Reply {
+    type Item = Result<Value, Value>;
+    type Error = RequestError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready(Ok(result))) => Ok(Async::Ready(result)),
+            Ok(Async::Ready(Err(e))) => Err(e),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(RequestError::ConnectionClosed),
+        }
+    }
+}
+
+/// A future that completes once a notification sent with a `ReconnectingClient` has been
+/// written to the connection.
+pub struct Delivery {
+    inner: oneshot::Receiver<Result<(), RequestError>>,
+}
+
+impl Future for Delivery {
+    type Item = ();
+    type Error = RequestError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.poll() {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => Err(RequestError::ConnectionClosed),
+        }
+    }
+}
+
+/// A stream of the changes to the state of a `ReconnectingClient`, see
+/// `ReconnectingClient::state_changes`.
+pub struct StateChanges {
+    inner: mpsc::UnboundedReceiver<State>,
+}
+
+impl Stream for StateChanges {
+    type Item = State;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+/// Passes the outcome of a request or notification sent with the current `Client` back to the
+/// caller, or abandons it if the caller has gone away.
+struct Forward<F: Future> {
+    inner: F,
+    tx: Option<oneshot::Sender<Result<F::Item, F::Error>>>,
+}
+
+impl<F: Future> Future for Forward<F> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.tx {
+            Some(ref mut tx) => {
+                if let Ok(Async::Ready(())) = tx.poll_cancel() {
+                    return Ok(Async::Ready(()));
+                }
+                match self.inner.poll() {
+                    Ok(Async::Ready(item)) => Ok(item),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => Err(e),
+                }
+            }
+            None => return Ok(Async::Ready(())),
+        };
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(result);
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+/// The connection of a `ReconnectingClient`.
+enum Link {
+    Connecting(Connection),
+    Connected(Client, Closed),
+    /// Waiting before dialing the server again.
+    Waiting(Option<Timeout>),
+}
+
+/// A change to the connection of a `ReconnectingClient`.
+enum Event {
+    Connected(Client),
+    Lost,
+    Retry,
+}
+
+/// The task that drives a `ReconnectingClient`.
+struct Supervisor<C> {
+    attempts: u32,
+    builder: ClientBuilder<C>,
+    commands_rx: mpsc::UnboundedReceiver<Command>,
+    handle: Handle,
+    initial_backoff: Duration,
+    link: Link,
+    max_backoff: Duration,
+    policy: Policy,
+    queue: VecDeque<Command>,
+    shared: Arc<Mutex<Shared>>,
+    target: Target,
+}
+
+impl<C> Supervisor<C>
+    where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    fn set_state(&mut self, state: State) {
+        debug!("ReconnectingClient: {:?}", state);
+        let mut shared = self.shared.lock().unwrap();
+        shared.state = state;
+        shared.subscribers.retain(|tx| mpsc::UnboundedSender::send(tx, state).is_ok());
+    }
+
+    fn dial(&mut self) {
+        let builder = self.builder.clone();
+        let connection = match self.target {
+            Target::Tcp(ref addr) => builder.connect(addr, &self.handle),
+            #[cfg(unix)]
+            Target::Unix(ref path) => builder.connect_unix(path, &self.handle),
+        };
+        self.link = Link::Connecting(connection);
+        self.set_state(State::Connecting);
+    }
+
+    fn wait(&mut self) {
+        self.set_state(State::Disconnected);
+        let delay = self.backoff();
+        self.attempts = self.attempts.saturating_add(1);
+        debug!("ReconnectingClient: dialing again in {:?}", delay);
+        let timeout = match Timeout::new(delay, &self.handle) {
+            Ok(timeout) => Some(timeout),
+            Err(e) => {
+                error!("ReconnectingClient: failed to time the backoff ({})", e);
+                None
+            }
+        };
+        self.link = Link::Waiting(timeout);
+    }
+
+    /// The delay before the next attempt to connect, with a random jitter of up to half of it.
+    fn backoff(&self) -> Duration {
+        let factor = 1u32.checked_shl(cmp::min(self.attempts, 31)).unwrap_or(u32::max_value());
+        let delay = self.initial_backoff.checked_mul(factor).unwrap_or(self.max_backoff);
+        let delay = cmp::min(delay, self.max_backoff);
+        let ms = delay.as_secs() * 1000 + delay.subsec_nanos() as u64 / 1_000_000;
+        let jitter = rand::thread_rng().gen_range(0, ms / 2 + 1);
+        Duration::from_millis(ms - jitter)
+    }
+
+    fn dispatch(&mut self, command: Command) {
+        if command.is_abandoned() {
+            debug!("ReconnectingClient: dropping abandoned request");
+            return;
+        }
+        let client = match self.link {
+            Link::Connected(ref client, _) => client.clone(),
+            _ => {
+                if let Policy::Queue(limit) = self.policy {
+                    if self.queue.len() >= limit {
+                        self.queue.retain(|command| !command.is_abandoned());
+                    }
+                }
+                match self.policy {
+                    Policy::Queue(limit) if self.queue.len() < limit => self.queue.push_back(command),
+                    _ => command.fail(RequestError::ConnectionClosed),
+                }
+                return;
+            }
+        };
+        match command {
+            Command::Request(method, params, timeout, tx) => {
+                let response: Response = match timeout {
+                    Some(timeout) => client.request_with_timeout(&method, &params, timeout),
+                    None => client.request(&method, &params),
+                };
+                self.handle.spawn(Forward {
+                    inner: response,
+                    tx: Some(tx),
+                });
+            }
+            Command::Notify(method, params, tx) => {
+                let ack: Ack = client.notify(&method, &params);
+                self.handle.spawn(Forward {
+                    inner: ack,
+                    tx: Some(tx),
+                });
+            }
+        }
+    }
+
+    /// Polls the connection, returning `true` if it has changed.
+    fn poll_link(&mut self) -> bool {
+        let event = match self.link {
+            Link::Connecting(ref mut connection) => {
+                match connection.poll() {
+                    Ok(Async::Ready(client)) => Event::Connected(client),
+                    Ok(Async::NotReady) => return false,
+                    Err(e) => {
+                        warn!("ReconnectingClient: failed to connect ({})", e);
+                        Event::Lost
+                    }
+                }
+            }
+            Link::Connected(_, ref mut closed) => {
+                match closed.poll() {
+                    Ok(Async::NotReady) => return false,
+                    _ => {
+                        warn!("ReconnectingClient: connection lost");
+                        Event::Lost
+                    }
+                }
+            }
+            Link::Waiting(Some(ref mut timeout)) => {
+                match timeout.poll() {
+                    Ok(Async::NotReady) => return false,
+                    _ => Event::Retry,
+                }
+            }
+            Link::Waiting(None) => Event::Retry,
+        };
+        match event {
+            Event::Connected(client) => {
+                self.attempts = 0;
+                let closed = client.closed();
+                self.link = Link::Connected(client, closed);
+                self.set_state(State::Connected);
+                while let Some(command) = self.queue.pop_front() {
+                    self.dispatch(command);
+                }
+            }
+            Event::Lost => self.wait(),
+            Event::Retry => self.dial(),
+        }
+        true
+    }
+}
+
+impl<C> Future for Supervisor<C>
+    where C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while self.poll_link() {}
+        loop {
+            match self.commands_rx.poll() {
+                Ok(Async::Ready(Some(command))) => self.dispatch(command),
+                Ok(Async::Ready(None)) | Err(()) => {
+                    debug!("ReconnectingClient: all the clients have been dropped");
+                    for command in self.queue.drain(..) {
+                        command.fail(RequestError::ConnectionClosed);
+                    }
+                    return Ok(Async::Ready(()));
+                }
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! A `Handler` that dispatches requests and notifications to functions registered by method name.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let router = Router::new()
+//!     .method("sayHello", |name: String| Ok(format!("Hello {}!", name)))
+//!     .method("add", |a: i64, b: i64| Ok(a + b))
+//!     .method_async("fetch", |key: String| store.get(key))
+//!     .notification("log", |msg: String| println!("{}", msg));
+//! let server = Server::new(router, stream);
+//! ```
+
+use futures::{future, BoxFuture, Future, IntoFuture};
+use message::RpcError;
+use rmpv::Value;
+use rmpv::ext;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use server::Handler;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+type MethodFn = Fn(&[Value]) -> BoxFuture<Value, Value> + Send + Sync;
+type NotificationFn = Fn(&[Value]) -> Result<(), Value> + Send + Sync;
+
+/// A function that can be registered with a `Router` to handle a request.
+///
+/// This is implemented for functions and closures with up to eight (8) parameters, where each
+/// parameter implements `DeserializeOwned` and the function returns a `Result<R, Value>` with `R`
+/// implementing `Serialize`. The number and types of the parameters of a request are checked
+/// before the function is called.
+pub trait Method<Args>: Send + Sync + 'static {
+    /// Calls the function with the parameters of a request.
+    fn call(&self, params: &[Value]) -> Result<Value, Value>;
+}
+
+/// A function that can be registered with a `Router` to handle a request asynchronously.
+///
+/// This is implemented for functions and closures with up to eight (8) parameters, where each
+/// parameter implements `DeserializeOwned` and the function returns an `IntoFuture` with an `Item`
+/// implementing `Serialize` and a `Value` as its `Error`. The number and types of the parameters
+/// of a request are checked before the function is called.
+pub trait AsyncMethod<Args>: Send + Sync + 'static {
+    /// Calls the function with the parameters of a request.
+    fn call(&self, params: &[Value]) -> BoxFuture<Value, Value>;
+}
+
+/// A function that can be registered with a `Router` to handle a notification.
+///
+/// This is implemented for functions and closures with up to eight (8) parameters, where each
+/// parameter implements `DeserializeOwned` and the function returns `()`.
+pub trait NotificationMethod<Args>: Send + Sync + 'static {
+    /// Calls the function with the parameters of a notification.
+    fn call(&self, params: &[Value]) -> Result<(), Value>;
+}
+
+fn check_arity(expected: usize, params: &[Value]) -> Result<(), Value> {
+    if params.len() == expected {
+        Ok(())
+    } else {
+        Err(RpcError::invalid_params(format!("Invalid params: expected {} parameter(s), got {}",
+                                             expected,
+                                             params.len()))
+            .into())
+    }
+}
+
+fn serialize_result<R: Serialize>(result: R) -> Result<Value, Value> {
+    ext::to_value(result)
+        .map_err(|e| RpcError::internal(format!("The result could not be serialized ({})", e)).into())
+}
+
+fn deserialize_param<T: DeserializeOwned>(index: usize, param: &Value) -> Result<T, Value> {
+    ext::from_value(param.clone()).map_err(|e| {
+        RpcError::invalid_params(format!("Invalid params: parameter {} has the wrong type ({})", index, e)).into()
+    })
+}
+
+macro_rules! impl_method {
+    ($len:expr; $($arg:ident),*) => {
+        impl<F, R, $($arg),*> Method<($($arg,)*)> for F
+            where F: Fn($($arg),*) -> Result<R, Value> + Send + Sync + 'static,
+                  R: Serialize,
+                  $($arg: DeserializeOwned),*
+        {
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn call(&self, params: &[Value]) -> Result<Value, Value> {
+                check_arity($len, params)?;
+                let mut index = 0;
+                $(
+                    let $arg = deserialize_param::<$arg>(index, &params[index])?;
+                    index += 1;
+                )*
+                let result = (self)($($arg),*)?;
+                serialize_result(result)
+            }
+        }
+
+        impl<F, Fut, $($arg),*> AsyncMethod<($($arg,)*)> for F
+            where F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+                  Fut: IntoFuture<Error = Value>,
+                  Fut::Future: Send + 'static,
+                  Fut::Item: Serialize + 'static,
+                  $($arg: DeserializeOwned),*
+        {
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn call(&self, params: &[Value]) -> BoxFuture<Value, Value> {
+                if let Err(e) = check_arity($len, params) {
+                    return Box::new(future::err(e));
+                }
+                let mut index = 0;
+                $(
+                    let $arg = match deserialize_param::<$arg>(index, &params[index]) {
+                        Ok(param) => param,
+                        Err(e) => return Box::new(future::err(e)),
+                    };
+                    index += 1;
+                )*
+                Box::new((self)($($arg),*).into_future().and_then(serialize_result))
+            }
+        }
+
+        impl<F, $($arg),*> NotificationMethod<($($arg,)*)> for F
+            where F: Fn($($arg),*) + Send + Sync + 'static,
+                  $($arg: DeserializeOwned),*
+        {
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn call(&self, params: &[Value]) -> Result<(), Value> {
+                check_arity($len, params)?;
+                let mut index = 0;
+                $(
+                    let $arg = deserialize_param::<$arg>(index, &params[index])?;
+                    index += 1;
+                )*
+                (self)($($arg),*);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl_method!(0;);
+impl_method!(1; A);
+impl_method!(2; A, B);
+impl_method!(3; A, B, C);
+impl_method!(4; A, B, C, D);
+impl_method!(5; A, B, C, D, E);
+impl_method!(6; A, B, C, D, E, F2);
+impl_method!(7; A, B, C, D, E, F2, G);
+impl_method!(8; A, B, C, D, E, F2, G, H);
+
+/// A `Handler` that dispatches requests and notifications by method name.
+///
+/// A request for a method that has not been registered is answered with a "method not found"
+/// error, and a request with the wrong number or types of parameters is answered with an
+/// "invalid params" error. Notifications for unknown methods or with invalid parameters are
+/// logged and dropped.
+#[derive(Clone, Default)]
+pub struct Router {
+    methods: HashMap<String, Arc<MethodFn>>,
+    notifications: HashMap<String, Arc<NotificationFn>>,
+}
+
+impl Router {
+    /// Creates a new `Router` without any methods.
+    pub fn new() -> Self {
+        Router {
+            methods: HashMap::new(),
+            notifications: HashMap::new(),
+        }
+    }
+
+    /// Registers a function to handle requests for the method.
+    ///
+    /// A function previously registered for the same method is replaced.
+    pub fn method<Args, M: Method<Args>>(mut self, name: &str, method: M) -> Self {
+        let method = move |params: &[Value]| -> BoxFuture<Value, Value> {
+            Box::new(future::result(method.call(params)))
+        };
+        self.methods.insert(name.to_owned(), Arc::new(method));
+        self
+    }
+
+    /// Registers a function returning a future to handle requests for the method.
+    ///
+    /// The request is answered when the future completes. A function previously registered for
+    /// the same method is replaced.
+    pub fn method_async<Args, M: AsyncMethod<Args>>(mut self, name: &str, method: M) -> Self {
+        let method = move |params: &[Value]| method.call(params);
+        self.methods.insert(name.to_owned(), Arc::new(method));
+        self
+    }
+
+    /// Registers a function to handle notifications for the method.
+    ///
+    /// A function previously registered for the same method is replaced.
+    pub fn notification<Args, N>(mut self, name: &str, notification: N) -> Self
+        where N: NotificationMethod<Args>
+    {
+        let notification = move |params: &[Value]| notification.call(params);
+        self.notifications.insert(name.to_owned(), Arc::new(notification));
+        self
+    }
+
+    /// Adds all of the methods and notifications registered with another `Router`.
+    ///
+    /// This can be used to assemble a service from independent modules. A method registered with
+    /// both routers is replaced by the one registered with `other`.
+    pub fn merge(mut self, other: Router) -> Self {
+        self.methods.extend(other.methods);
+        self.notifications.extend(other.notifications);
+        self
+    }
+}
+
+impl Handler for Router {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        match self.methods.get(method) {
+            Some(m) => Box::new(m(params).then(Ok)),
+            None => {
+                debug!("Router: method not found ({})", method);
+                Box::new(future::ok(Err(RpcError::method_not_found(method).into())))
+            }
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &[Value]) -> BoxFuture<(), Self::Error> {
+        match self.notifications.get(method) {
+            Some(n) => {
+                if let Err(e) = n(params) {
+                    warn!("Router: dropping notification '{}' ({})", method, e);
+                }
+            }
+            None => debug!("Router: notification method not found ({})", method),
+        }
+        Box::new(future::ok(()))
+    }
+}
@@ -0,0 +1,143 @@
+//! A counting semaphore for bounding the number of queued messages.
+
+use futures::Async;
+use futures::task::{self, Task};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+struct Inner {
+    next_waiter: usize,
+    /// The IDs of the `Acquire`s that have been notified of a permit, which is reserved for them
+    /// until they take it or are dropped.
+    notified: HashSet<usize>,
+    permits: usize,
+    /// The tasks waiting for a permit, by the ID of their `Acquire`.
+    tasks: HashMap<usize, Task>,
+    /// The IDs of the waiting `Acquire`s, in the order they started waiting. An ID is left behind
+    /// when its `Acquire` stops waiting, and is skipped when it reaches the front.
+    waiters: VecDeque<usize>,
+}
+
+impl Inner {
+    /// Reserves a permit for the task that has been waiting the longest, if any, and notifies it.
+    fn notify_next(&mut self) {
+        while let Some(id) = self.waiters.pop_front() {
+            if let Some(task) = self.tasks.remove(&id) {
+                self.notified.insert(id);
+                task.notify();
+                return;
+            }
+        }
+    }
+
+    /// Indicates if a permit is free, and not reserved for a task that has been waiting for it.
+    fn is_free(&self) -> bool {
+        self.permits > self.notified.len() && self.tasks.is_empty()
+    }
+}
+
+/// A counting semaphore shared between futures.
+///
+/// A permit is returned to the semaphore when it is dropped, which notifies the task that has been
+/// waiting the longest for a permit. The permits are handed out in the order the tasks started
+/// waiting, so a task cannot take a permit ahead of the ones already waiting for it.
+#[derive(Clone)]
+pub struct Semaphore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the number of permits.
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            inner: Arc::new(Mutex::new(Inner {
+                next_waiter: 0,
+                notified: HashSet::new(),
+                permits: permits,
+                tasks: HashMap::new(),
+                waiters: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Acquires a permit if one is available and no task is waiting for one, without waiting.
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.is_free() {
+            return None;
+        }
+        inner.permits -= 1;
+        Some(Permit { semaphore: self.clone() })
+    }
+
+    /// Creates a handle to wait for a permit.
+    pub fn acquire(&self) -> Acquire {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_waiter = inner.next_waiter.wrapping_add(1);
+        Acquire {
+            id: inner.next_waiter,
+            semaphore: self.clone(),
+        }
+    }
+}
+
+/// A handle to wait for a permit of a `Semaphore`.
+///
+/// The handle waits in line at most once, however many times it is polled. Dropping it leaves the
+/// line, and passes on the notification it may have received to the next task in line.
+pub struct Acquire {
+    id: usize,
+    semaphore: Semaphore,
+}
+
+impl Acquire {
+    /// Acquires a permit, or schedules the current task to be notified when one is released.
+    ///
+    /// This must be called from within the context of a task.
+    pub fn poll(&mut self) -> Async<Permit> {
+        let mut inner = self.semaphore.inner.lock().unwrap();
+        let id = self.id;
+        if !inner.notified.remove(&id) && !inner.is_free() {
+            let queued = match inner.tasks.get_mut(&id) {
+                Some(task) => {
+                    if !task.will_notify_current() {
+                        *task = task::current();
+                    }
+                    true
+                }
+                None => false,
+            };
+            if !queued {
+                inner.tasks.insert(id, task::current());
+                inner.waiters.push_back(id);
+            }
+            return Async::NotReady;
+        }
+        inner.permits -= 1;
+        Async::Ready(Permit { semaphore: self.semaphore.clone() })
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        let mut inner = self.semaphore.inner.lock().unwrap();
+        inner.tasks.remove(&self.id);
+        // The task was notified of a permit it will not take.
+        if inner.notified.remove(&self.id) {
+            inner.notify_next();
+        }
+    }
+}
+
+/// A permit acquired from a `Semaphore`.
+pub struct Permit {
+    semaphore: Semaphore,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut inner = self.semaphore.inner.lock().unwrap();
+        inner.permits += 1;
+        inner.notify_next();
+    }
+}
@@ -3,14 +3,30 @@
 //! Building blocks for building a `Framed-MessagePack-RPC` server.
 
 use codec::Codec;
-use futures::{Async, BoxFuture, Future, Poll, Sink, Stream};
-use message::{Message, Response};
+use dispatch::{self, format_error, Dispatch, Dispatcher, Pushed};
+use futures::{future, Async, AsyncSink, BoxFuture, Future, Poll, Sink, Stream};
+use futures::future::Shared;
+use futures::sync::{mpsc, oneshot};
+use message::{Message, Request, Response, RpcError};
 use rmpv::Value;
+use semaphore::{Acquire, Permit, Semaphore};
 use std::io;
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::error::Error;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Handle, Remote, Timeout};
 use tokio_io::{AsyncRead, AsyncWrite};
-use tokio_io::codec::Framed;
+use tokio_io::codec::{Decoder, Encoder, Framed};
+#[cfg(unix)]
+use tokio_uds::{UnixListener, UnixStream};
+
+pub use dispatch::Notifier;
 
 /// The `Handler` trait defines how the server handles the requests and notifications it receives.
 pub trait Handler: Clone {
@@ -27,110 +43,781 @@ pub trait Handler: Clone {
     ///
     /// The framing is handled automatically by the codec.
     fn handle_notification(&mut self, method: &str, params: &[Value]) -> BoxFuture<(), Self::Error>;
+
+    /// Called when a `Server` or a `Peer` starts serving a connection with this handler.
+    ///
+    /// The `notifier` pushes notifications to the other end of the connection, e.g. to implement
+    /// a change feed. The default implementation drops it.
+    fn connected(&mut self, notifier: Notifier) {
+        let _ = notifier;
+    }
+
+    /// Called when the `Server` or `Peer` stops serving the connection, whether it has completed,
+    /// failed, or been dropped.
+    ///
+    /// This can be used to release the resources held for the connection since `connected`. The
+    /// default implementation does nothing.
+    fn disconnected(&mut self) {}
+}
+
+/// A function that formats the error of a failed request handler as the error of its response,
+/// see `Server::with_error_formatter`.
+pub type ErrorFormatter = Fn(&Error) -> Value + Send + Sync;
+
+type Signal = Shared<oneshot::Receiver<()>>;
+
+/// What a server does with a request that would exceed its concurrency limits, see
+/// `Server::with_max_in_flight` and `Server::with_global_limit`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overload {
+    /// Holds the request, and stops reading from the connection until it can be handled.
+    Backpressure,
+    /// Responds to the request at once with an `RpcError` with the `OVERLOADED` code.
+    Reject,
+}
+
+/// A limit on the number of requests handled at once across several servers.
+///
+/// The limit can be cloned to share it between the servers.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    semaphore: Semaphore,
+}
+
+impl ConcurrencyLimit {
+    /// Creates a new `ConcurrencyLimit` that allows `max` requests to be handled at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "The limit must be at least one");
+        ConcurrencyLimit { semaphore: Semaphore::new(max) }
+    }
+}
+
+/// The concurrency limits of a server.
+#[derive(Clone)]
+struct Limits {
+    global: Option<ConcurrencyLimit>,
+    max_in_flight: Option<usize>,
+    overload: Overload,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            global: None,
+            max_in_flight: None,
+            overload: Overload::Backpressure,
+        }
+    }
+}
+
+/// A handle to gracefully shut down one or more servers.
+///
+/// When triggered, each server attached to the handle with `Server::with_shutdown` stops reading
+/// messages from its client and waits for its pending requests and notifications to complete.
+/// The responses are flushed and then the write half of the connection is shut down. Any
+/// requests still pending when the grace period expires are dropped without a response.
+///
+/// The handle can be cloned and sent to other threads, e.g. a signal handler.
+#[derive(Clone)]
+pub struct Shutdown {
+    drain_rx: Signal,
+    drain_tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    expire_rx: Signal,
+    expire_tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    remote: Remote,
+}
+
+impl Shutdown {
+    /// Creates a new `Shutdown` handle.
+    ///
+    /// The grace period is timed on the event loop of the `handle`.
+    pub fn new(handle: &Handle) -> Self {
+        let (drain_tx, drain_rx) = oneshot::channel();
+        let (expire_tx, expire_rx) = oneshot::channel();
+        Shutdown {
+            drain_rx: drain_rx.shared(),
+            drain_tx: Arc::new(Mutex::new(Some(drain_tx))),
+            expire_rx: expire_rx.shared(),
+            expire_tx: Arc::new(Mutex::new(Some(expire_tx))),
+            remote: handle.remote().clone(),
+        }
+    }
+
+    /// Triggers the shutdown of all the attached servers.
+    ///
+    /// The servers wait up to the `grace` period for their pending requests to complete. Only the
+    /// first trigger has an effect.
+    pub fn shutdown(&self, grace: Duration) {
+        let drain_tx = match self.drain_tx.lock().unwrap().take() {
+            Some(tx) => tx,
+            None => return,
+        };
+        debug!("Server: shutting down (grace = {:?})", grace);
+        let _ = drain_tx.send(());
+        let expire_tx = self.expire_tx.clone();
+        self.remote.spawn(move |handle| {
+            let expire = move || {
+                if let Some(tx) = expire_tx.lock().unwrap().take() {
+                    debug!("Server: shutdown grace period expired");
+                    let _ = tx.send(());
+                }
+            };
+            match Timeout::new(grace, handle) {
+                Ok(timeout) => future::Either::A(timeout.then(move |_| {
+                    expire();
+                    Ok(())
+                })),
+                Err(e) => {
+                    error!("Server: failed to time the shutdown grace period ({})", e);
+                    expire();
+                    future::Either::B(future::ok(()))
+                }
+            }
+        });
+    }
+
+    /// Indicates if the shutdown has been triggered.
+    pub fn is_triggered(&self) -> bool {
+        self.drain_tx.lock().unwrap().is_none()
+    }
+}
+
+/// Polls a shutdown signal, returning `true` once it has fired.
+///
+/// The signal is cleared once it has fired, or if all of the `Shutdown` handles have been dropped
+/// without triggering it.
+fn poll_signal(signal: &mut Option<Signal>) -> bool {
+    let fired = match *signal {
+        Some(ref mut s) => {
+            match s.poll() {
+                Ok(Async::Ready(_)) => true,
+                Ok(Async::NotReady) => return false,
+                Err(_) => false,
+            }
+        }
+        None => return false,
+    };
+    *signal = None;
+    fired
 }
 
 /// A Framed-Msgpack-RPC server that can handle requests and notifications.
-pub struct Server<T: AsyncRead + AsyncWrite, H: Handler> {
-    handler: H,
-    io: Framed<T, Codec>,
-    request_tasks: HashMap<u32, BoxFuture<Result<H::T, H::E>, H::Error>>,
-    notification_tasks: Vec<BoxFuture<(), H::Error>>,
+pub struct Server<T: AsyncRead + AsyncWrite, H: Handler, C = Codec> {
+    acquire: Option<Acquire>,
+    deadline: Option<Signal>,
+    drain: Option<Signal>,
+    draining: bool,
+    dispatcher: Dispatcher<H>,
+    io: Framed<T, C>,
+    limits: Limits,
+    notifier: Notifier,
+    parked: Option<Request>,
+    pushed: Pushed,
+    unsent: VecDeque<Message>,
 }
 
 impl<T: AsyncRead + AsyncWrite + 'static, H: Handler + 'static> Server<T, H> {
     /// Creates a new `Server`.
     pub fn new(handler: H, io: T) -> Self {
+        Server::with_codec(handler, io, Codec::new())
+    }
+}
+
+impl<T, H, C> Server<T, H, C>
+    where T: AsyncRead + AsyncWrite + 'static,
+          H: Handler + 'static,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+{
+    /// Creates a new `Server` that transmits messages with the given codec.
+    ///
+    /// This can be used to limit the size of the frames accepted from a client, see
+    /// `CodecBuilder::max_frame_len`, or to communicate with peers that do not frame messages,
+    /// see `UnframedCodec`.
+    pub fn with_codec(mut handler: H, io: T, codec: C) -> Self {
+        let (notifier, pushed) = dispatch::notifier();
+        handler.connected(notifier.clone());
         Server {
-            handler: handler,
-            io: io.framed(Codec::new()),
-            request_tasks: HashMap::new(),
-            notification_tasks: Vec::new(),
+            acquire: None,
+            deadline: None,
+            drain: None,
+            draining: false,
+            dispatcher: Dispatcher::new(handler),
+            io: io.framed(codec),
+            limits: Limits::default(),
+            notifier: notifier,
+            parked: None,
+            pushed: pushed,
+            unsent: VecDeque::new(),
         }
     }
 
-    fn handle_msg(&mut self, msg: Message) {
+    /// Sets the function that formats the error of a failed request handler as the error of its
+    /// response.
+    ///
+    /// The error is logged, and the server keeps serving the connection. By default, the error of
+    /// the response is an `RpcError` with the `INTERNAL_ERROR` code and the message of the error.
+    pub fn with_error_formatter<F>(mut self, formatter: F) -> Self
+        where F: Fn(&Error) -> Value + Send + Sync + 'static
+    {
+        self.dispatcher.set_error_formatter(Arc::new(formatter));
+        self
+    }
+
+    /// Limits the number of requests from the client that are handled at once.
+    ///
+    /// The requests beyond the limit are handled according to the `Overload` policy, see
+    /// `Server::with_overload`. By default, the number of requests is not limited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn with_max_in_flight(mut self, max: usize) -> Self {
+        assert!(max > 0, "The limit must be at least one");
+        self.limits.max_in_flight = Some(max);
+        self
+    }
+
+    /// Limits the number of requests that are handled at once by all the servers sharing the
+    /// `limit`.
+    pub fn with_global_limit(mut self, limit: &ConcurrencyLimit) -> Self {
+        self.limits.global = Some(limit.clone());
+        self
+    }
+
+    /// Sets what is done with the requests beyond the concurrency limits. The default is
+    /// `Overload::Backpressure`.
+    pub fn with_overload(mut self, overload: Overload) -> Self {
+        self.limits.overload = overload;
+        self
+    }
+
+    /// Returns a handle to push notifications to the client.
+    pub fn notifier(&self) -> Notifier {
+        self.notifier.clone()
+    }
+
+    /// Attaches the server to a `Shutdown` handle to gracefully shut it down.
+    pub fn with_shutdown(mut self, shutdown: &Shutdown) -> Self {
+        self.drain = Some(shutdown.drain_rx.clone());
+        self.deadline = Some(shutdown.expire_rx.clone());
+        self
+    }
+
+    fn handle_msg(&mut self, msg: Message) -> io::Result<()> {
         trace!("Server: handle message");
         match msg {
             Message::Request(request) => {
                 debug!("Server: message is a request");
-                let method = request.method.as_str();
-                let params = request.params;
-                trace!("Server: request (method = {}, params = {:?})", method, params);
-                let response = self.handler.handle_request(method, &params);
-                self.request_tasks.insert(request.id, response);
+                return self.admit(request);
             }
             Message::Notification(notification) => {
                 debug!("Server: message is a notification");
-                let method = notification.method.as_str();
-                let params = notification.params;
-                trace!("Server: notification (method = {}, params = {:?})", method, params);
-                let outcome = self.handler.handle_notification(method, &params);
-                self.notification_tasks.push(outcome);
+                self.dispatcher.handle_notification(notification);
             }
             Message::Response(response) => {
                 debug!("Server: message is a response");
                 trace!("Server: response ({:?})", response);
-                return;
             }
         }
+        Ok(())
     }
 
-    fn process_notifications(&mut self) {
-        trace!("Server: process notifications");
-        let mut done = vec![];
-        for (idx, task) in self.notification_tasks.iter_mut().enumerate() {
-            match task.poll().unwrap() {
-                Async::Ready(_) => done.push(idx),
-                Async::NotReady => continue,
+    /// Passes a request to the handler if it is within the concurrency limits, or else handles
+    /// it according to the `Overload` policy.
+    fn admit(&mut self, request: Request) -> io::Result<()> {
+        let local_full = self.limits.max_in_flight.map_or(false, |max| self.dispatcher.pending_requests() >= max);
+        // A permit is not taken ahead of the connections already waiting for one.
+        let permit = match self.limits.global {
+            Some(ref global) if !local_full => global.semaphore.try_acquire(),
+            _ => None,
+        };
+        if local_full || (self.limits.global.is_some() && permit.is_none()) {
+            match self.limits.overload {
+                Overload::Backpressure => {
+                    debug!("Server: holding request until it is within the limits (id = {})", request.id);
+                    self.parked = Some(request);
+                }
+                Overload::Reject => {
+                    warn!("Server: rejecting request, overloaded (id = {})", request.id);
+                    self.send(Message::Response(Response {
+                        id: request.id,
+                        result: Err(RpcError::overloaded().into()),
+                    }))?;
+                }
             }
+            return Ok(());
         }
-        for idx in done.iter().rev() {
-            self.notification_tasks.remove(*idx);
-        }
+        self.dispatch(request, permit);
+        Ok(())
     }
 
-    fn process_requests(&mut self) {
-        trace!("Server: process requests");
-        let mut done = vec![];
-        for (id, task) in &mut self.request_tasks {
-            match task.poll().unwrap() {
-                Async::Ready(response) => {
-                    let msg = Message::Response(Response {
-                        id: *id,
-                        result: response.map(|v| v.into()).map_err(|e| e.into()),
-                    });
-                    done.push(*id);
-                    if !self.io.start_send(msg).unwrap().is_ready() {
-                        panic!("the sink is full")
+    /// Passes the held request to the handler once it is within the concurrency limits.
+    ///
+    /// Returns `true` if the request was passed to the handler.
+    fn poll_parked(&mut self) -> bool {
+        let request = match self.parked.take() {
+            Some(request) => request,
+            None => return false,
+        };
+        if self.limits.max_in_flight.map_or(false, |max| self.dispatcher.pending_requests() >= max) {
+            // The handler notifies the task when one of the requests completes.
+            self.parked = Some(request);
+            return false;
+        }
+        let permit = match self.limits.global {
+            Some(ref global) => {
+                if self.acquire.is_none() {
+                    self.acquire = Some(global.semaphore.acquire());
+                }
+                match self.acquire.as_mut().map(|acquire| acquire.poll()) {
+                    Some(Async::Ready(permit)) => {
+                        self.acquire = None;
+                        Some(permit)
+                    }
+                    _ => {
+                        self.parked = Some(request);
+                        return false;
                     }
                 }
-                Async::NotReady => continue,
+            }
+            None => None,
+        };
+        self.dispatch(request, permit);
+        true
+    }
+
+    fn dispatch(&mut self, request: Request, permit: Option<Permit>) {
+        // The permit is held by the task of the request, as the client may reuse request IDs.
+        self.dispatcher.handle_request_with_permit(request, permit);
+    }
+
+    fn process_pushed(&mut self) -> io::Result<()> {
+        // The server holds a sender, so the channel never ends.
+        while self.unsent.is_empty() {
+            match self.pushed.poll() {
+                Ok(Async::Ready(Some(notification))) => self.send(Message::Notification(notification))?,
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) | Err(()) => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn process_requests(&mut self) -> io::Result<()> {
+        trace!("Server: process requests");
+        // The remaining results are left with their tasks until the transport accepts more
+        // messages.
+        while self.unsent.is_empty() {
+            match self.dispatcher.poll_response() {
+                Some(response) => self.send(Message::Response(response))?,
+                None => break,
             }
         }
-        for idx in done.iter_mut().rev() {
-            let _ = self.request_tasks.remove(idx);
+        Ok(())
+    }
+
+    /// Sends a message, queueing it if the transport is not ready to accept it.
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        if !self.unsent.is_empty() {
+            self.unsent.push_back(msg);
+            return Ok(());
+        }
+        if let AsyncSink::NotReady(msg) = self.io.start_send(msg)? {
+            self.unsent.push_back(msg);
+        }
+        Ok(())
+    }
+
+    /// Sends the queued messages and flushes the transport.
+    fn flush(&mut self) -> Poll<(), io::Error> {
+        while let Some(msg) = self.unsent.pop_front() {
+            if let AsyncSink::NotReady(msg) = self.io.start_send(msg)? {
+                self.unsent.push_front(msg);
+                break;
+            }
+        }
+        try_ready!(self.io.poll_complete());
+        if self.unsent.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
         }
     }
 }
 
-impl<T: AsyncRead + AsyncWrite + 'static, H: Handler + 'static> Future for Server<T, H> {
+impl<T, H, C> Future for Server<T, H, C>
+    where T: AsyncRead + AsyncWrite + 'static,
+          H: Handler + 'static,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + 'static
+{
     type Item = ();
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if !self.draining && poll_signal(&mut self.drain) {
+            debug!("Server: draining");
+            self.draining = true;
+        }
         loop {
-            match self.io.poll().unwrap() {
-                Async::Ready(Some(msg)) => self.handle_msg(msg),
-                Async::Ready(None) => {
+            self.poll_parked();
+            // Stop reading while responses are waiting for the transport, so a client that does
+            // not read its responses cannot make the server buffer them without bound, and while a
+            // request is held back by the concurrency limits.
+            while !self.draining && self.unsent.is_empty() && self.parked.is_none() {
+                match self.io.poll() {
+                    Ok(Async::Ready(Some(msg))) => {
+                        if let Err(e) = self.handle_msg(msg) {
+                            error!("Server: dropping connection ({})", e);
+                            return Err(e);
+                        }
+                    }
+                    Ok(Async::Ready(None)) => {
+                        // The client may have only closed its write half, and still be waiting for
+                        // the responses to its requests.
+                        debug!("Server: connection closed by the client");
+                        self.draining = true;
+                    }
+                    Ok(Async::NotReady) => break,
+                    Err(e) => {
+                        error!("Server: dropping connection ({})", e);
+                        return Err(e);
+                    }
+                }
+            }
+            self.dispatcher.poll_notifications();
+            if let Err(e) = self.process_requests().and_then(|_| self.process_pushed()) {
+                error!("Server: dropping connection ({})", e);
+                return Err(e);
+            }
+            let unparked = self.poll_parked();
+            if self.draining {
+                if poll_signal(&mut self.deadline) {
+                    warn!("Server: dropping {} pending request(s) and {} pending notification(s)",
+                          self.dispatcher.pending_requests(),
+                          self.dispatcher.pending_notifications());
+                    self.dispatcher.clear();
+                    self.acquire = None;
+                    self.parked = None;
+                }
+                if self.dispatcher.is_empty() && self.parked.is_none() {
+                    try_ready!(self.flush());
+                    try_ready!(self.io.close());
+                    debug!("Server: shut down");
                     return Ok(Async::Ready(()));
                 }
-                Async::NotReady => break,
+            }
+            let blocked = !self.unsent.is_empty();
+            if let Err(e) = self.flush() {
+                error!("Server: dropping connection ({})", e);
+                return Err(e);
+            }
+            // Messages and results may have been left behind while the transport was full, and
+            // more requests may be waiting to be read once a held request has been handled.
+            if (!blocked || !self.unsent.is_empty()) && !unparked {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite, H: Handler, C> Drop for Server<T, H, C> {
+    fn drop(&mut self) {
+        self.dispatcher.handler_mut().disconnected();
+    }
+}
+
+/// The delay before accepting connections again after an error, e.g. too many open files.
+const ACCEPT_ERROR_DELAY_MS: u64 = 100;
+
+/// Binds a TCP listener to the address and serves each connection with a clone of the handler.
+///
+/// This is a shortcut for `ServerBuilder::new(handler).bind(addr, handle)`.
+pub fn serve<H>(addr: &SocketAddr, handler: H, handle: &Handle) -> io::Result<Listener<TcpStream, H>>
+    where H: Handler + 'static
+{
+    ServerBuilder::new(handler).bind(addr, handle)
+}
+
+/// Binds a Unix domain socket listener to the path and serves each connection with a clone of
+/// the handler.
+///
+/// This is a shortcut for `ServerBuilder::new(handler).bind_unix(path, handle)`.
+#[cfg(unix)]
+pub fn serve_unix<P, H>(path: P, handler: H, handle: &Handle) -> io::Result<Listener<UnixStream, H>>
+    where P: AsRef<Path>,
+          H: Handler + 'static
+{
+    ServerBuilder::new(handler).bind_unix(path, handle)
+}
+
+/// A builder for a `Listener`, which accepts connections and serves each one with a `Server`.
+pub struct ServerBuilder<H, C = Codec> {
+    codec: C,
+    formatter: Arc<ErrorFormatter>,
+    handler: H,
+    limits: Limits,
+}
+
+impl<H: Handler + 'static> ServerBuilder<H> {
+    /// Creates a new `ServerBuilder`.
+    ///
+    /// Each connection is served with a clone of the handler.
+    pub fn new(handler: H) -> Self {
+        ServerBuilder {
+            codec: Codec::new(),
+            formatter: Arc::new(format_error),
+            handler: handler,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl<H, C> ServerBuilder<H, C>
+    where H: Handler + 'static,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    /// Sets the codec used to transmit messages, see `Server::with_codec`.
+    pub fn codec<C2>(self, codec: C2) -> ServerBuilder<H, C2> {
+        ServerBuilder {
+            codec: codec,
+            formatter: self.formatter,
+            handler: self.handler,
+            limits: self.limits,
+        }
+    }
+
+    /// Limits the number of requests from each client that are handled at once, see
+    /// `Server::with_max_in_flight`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        assert!(max > 0, "The limit must be at least one");
+        self.limits.max_in_flight = Some(max);
+        self
+    }
+
+    /// Limits the number of requests that are handled at once by all the connections, and any
+    /// other server sharing the `limit`.
+    pub fn global_limit(mut self, limit: &ConcurrencyLimit) -> Self {
+        self.limits.global = Some(limit.clone());
+        self
+    }
+
+    /// Sets what is done with the requests beyond the concurrency limits, see
+    /// `Server::with_overload`.
+    pub fn overload(mut self, overload: Overload) -> Self {
+        self.limits.overload = overload;
+        self
+    }
+
+    /// Sets the function that formats the error of a failed request handler as the error of its
+    /// response, see `Server::with_error_formatter`.
+    pub fn error_formatter<F>(mut self, formatter: F) -> Self
+        where F: Fn(&Error) -> Value + Send + Sync + 'static
+    {
+        self.formatter = Arc::new(formatter);
+        self
+    }
+
+    /// Binds a TCP listener to the address.
+    ///
+    /// The returned `Listener` must be run on the event loop of the `handle` to accept
+    /// connections.
+    pub fn bind(self, addr: &SocketAddr, handle: &Handle) -> io::Result<Listener<TcpStream, H, C>> {
+        let listener = TcpListener::bind(addr, handle)?;
+        let local_addr = listener.local_addr()?;
+        debug!("Server: listening on {}", local_addr);
+        let incoming = listener.incoming().map(|(stream, addr)| {
+            debug!("Server: accepted connection from {}", addr);
+            stream
+        });
+        Ok(self.listen(Box::new(incoming), Some(local_addr), handle))
+    }
+
+    /// Binds a Unix domain socket listener to the path.
+    ///
+    /// The returned `Listener` must be run on the event loop of the `handle` to accept
+    /// connections. The socket file is not removed when the listener is dropped.
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(self, path: P, handle: &Handle) -> io::Result<Listener<UnixStream, H, C>> {
+        let listener = UnixListener::bind(&path, handle)?;
+        debug!("Server: listening on {}", path.as_ref().display());
+        let incoming = listener.incoming().map(|(stream, _)| {
+            debug!("Server: accepted connection");
+            stream
+        });
+        Ok(self.listen(Box::new(incoming), None, handle))
+    }
+
+    fn listen<T>(self,
+                 incoming: Box<Stream<Item = T, Error = io::Error>>,
+                 local_addr: Option<SocketAddr>,
+                 handle: &Handle) -> Listener<T, H, C>
+        where T: AsyncRead + AsyncWrite + 'static
+    {
+        let shutdown = Shutdown::new(handle);
+        let (done_tx, done_rx) = mpsc::unbounded();
+        Listener {
+            accept_delay: None,
+            codec: self.codec,
+            connection_id: 0,
+            connections: Connections(Arc::new(AtomicUsize::new(0))),
+            done_rx: done_rx,
+            done_tx: done_tx,
+            drain: Some(shutdown.drain_rx.clone()),
+            formatter: self.formatter,
+            handle: handle.clone(),
+            handler: self.handler,
+            incoming: Some(incoming),
+            limits: self.limits,
+            local_addr: local_addr,
+            shutdown: shutdown,
+        }
+    }
+}
+
+/// A handle to the number of live connections of a `Listener`.
+#[derive(Clone, Debug)]
+pub struct Connections(Arc<AtomicUsize>);
+
+impl Connections {
+    /// Gets the number of live connections.
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A future that accepts connections and spawns a `Server` for each one.
+///
+/// Errors that occur on a connection are logged and only close that connection. The future
+/// completes after its `Shutdown` handle is triggered and all of the connections have been shut
+/// down.
+pub struct Listener<T, H, C = Codec> {
+    accept_delay: Option<Timeout>,
+    codec: C,
+    connection_id: u64,
+    connections: Connections,
+    done_rx: mpsc::UnboundedReceiver<()>,
+    done_tx: mpsc::UnboundedSender<()>,
+    drain: Option<Signal>,
+    formatter: Arc<ErrorFormatter>,
+    handle: Handle,
+    handler: H,
+    incoming: Option<Box<Stream<Item = T, Error = io::Error>>>,
+    limits: Limits,
+    local_addr: Option<SocketAddr>,
+    shutdown: Shutdown,
+}
+
+impl<T, H, C> Listener<T, H, C>
+    where T: AsyncRead + AsyncWrite + 'static,
+          H: Handler + 'static,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    /// Gets a handle to shut down the listener and all of its connections.
+    ///
+    /// Once triggered, the listener stops accepting connections.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Gets a handle to the number of live connections.
+    pub fn connections(&self) -> Connections {
+        self.connections.clone()
+    }
+
+    /// Gets the local address of a TCP listener.
+    ///
+    /// This is `None` for listeners that are not bound to a socket address.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    fn spawn(&mut self, io: T) {
+        self.connection_id += 1;
+        let id = self.connection_id;
+        let connections = self.connections.clone();
+        let done_tx = self.done_tx.clone();
+        let mut server = Server::with_codec(self.handler.clone(), io, self.codec.clone());
+        server.dispatcher.set_error_formatter(self.formatter.clone());
+        server.limits = self.limits.clone();
+        let server = server.with_shutdown(&self.shutdown)
+            .then(move |result| {
+                match result {
+                    Ok(()) => debug!("Server: connection {} closed", id),
+                    Err(e) => warn!("Server: connection {} failed ({})", id, e),
+                }
+                (connections.0).fetch_sub(1, Ordering::SeqCst);
+                let _ = mpsc::UnboundedSender::send(&done_tx, ());
+                Ok(())
+            });
+        (self.connections.0).fetch_add(1, Ordering::SeqCst);
+        trace!("Server: connection {} opened ({} live)", id, self.connections.count());
+        self.handle.spawn(server);
+    }
+
+    fn poll_accept(&mut self) {
+        loop {
+            if let Some(mut delay) = self.accept_delay.take() {
+                if let Ok(Async::NotReady) = delay.poll() {
+                    self.accept_delay = Some(delay);
+                    return;
+                }
+            }
+            let result = match self.incoming {
+                Some(ref mut incoming) => incoming.poll(),
+                None => return,
+            };
+            match result {
+                Ok(Async::Ready(Some(io))) => self.spawn(io),
+                Ok(Async::Ready(None)) => {
+                    debug!("Server: listener closed");
+                    self.incoming = None;
+                    return;
+                }
+                Ok(Async::NotReady) => return,
+                Err(e) => {
+                    error!("Server: failed to accept a connection ({})", e);
+                    let delay = Duration::from_millis(ACCEPT_ERROR_DELAY_MS);
+                    match Timeout::new(delay, &self.handle) {
+                        Ok(delay) => self.accept_delay = Some(delay),
+                        Err(e) => {
+                            error!("Server: stopped accepting connections ({})", e);
+                            self.incoming = None;
+                            return;
+                        }
+                    }
+                }
             }
         }
-        self.process_notifications();
-        self.process_requests();
-        self.io.poll_complete().unwrap();
-        Ok(Async::NotReady)
     }
 }
 
+impl<T, H, C> Future for Listener<T, H, C>
+    where T: AsyncRead + AsyncWrite + 'static,
+          H: Handler + 'static,
+          C: Decoder<Item = Message, Error = io::Error> + Encoder<Item = Message, Error = io::Error> + Clone + 'static
+{
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if poll_signal(&mut self.drain) {
+            debug!("Server: stopped accepting connections");
+            self.incoming = None;
+            self.accept_delay = None;
+        }
+        self.poll_accept();
+        while let Ok(Async::Ready(Some(()))) = self.done_rx.poll() {}
+        if self.incoming.is_none() && self.connections.count() == 0 {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
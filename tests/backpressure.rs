@@ -0,0 +1,86 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+mod common;
+
+use framed_msgpack_rpc::client::ClientBuilder;
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::ServerBuilder;
+use futures::Future;
+use futures::future::join_all;
+use rmpv::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_core::reactor::Core;
+
+/// Large enough for a single message to fill the socket buffers.
+const PAYLOAD_LEN: usize = 256 * 1024;
+
+#[test]
+fn large_messages_with_small_capacity() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    let router = Router::new()
+        .method("echo", |data: String| Ok(data))
+        .notification("store", move |data: String| {
+            counter.fetch_add(data.len(), Ordering::SeqCst);
+        });
+    let addr = common::serve(&core, ServerBuilder::new(router));
+    let client = core.run(ClientBuilder::new().capacity(2).connect(&addr, &handle)).unwrap();
+
+    let payload = "x".repeat(PAYLOAD_LEN);
+    let acks: Vec<_> = (0..16).map(|_| client.notify("store", &[Value::from(payload.as_str())])).collect();
+    let responses: Vec<_> = (0..16)
+        .map(|_| client.request("echo", &[Value::from(payload.as_str())]))
+        .collect();
+    core.run(join_all(acks)).unwrap();
+    for response in core.run(join_all(responses)).unwrap() {
+        assert_eq!(response, Ok(Value::from(payload.as_str())));
+    }
+    assert_eq!(received.load(Ordering::SeqCst), 16 * PAYLOAD_LEN);
+}
+
+#[test]
+fn notifications_beyond_capacity_wait_in_their_acks() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    let router = Router::new()
+        .method("echo", |data: String| Ok(data))
+        .notification("count", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+    let addr = common::serve(&core, ServerBuilder::new(router));
+    let client = core.run(ClientBuilder::new().capacity(1).connect(&addr, &handle)).unwrap();
+
+    // The first notification takes the only room in the queue, so the second one is held by its
+    // `Ack`, and is discarded with it.
+    let first = client.notify("count", &[]);
+    drop(client.notify("count", &[]));
+    let acks: Vec<_> = (0..8).map(|_| client.notify("count", &[])).collect();
+    core.run(first.join(join_all(acks))).unwrap();
+    core.run(client.request("echo", &[Value::from("done")])).unwrap().unwrap();
+    assert_eq!(received.load(Ordering::SeqCst), 9);
+}
+
+#[test]
+fn held_requests_are_sent_after_the_client_is_dropped() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let router = Router::new().method("echo", |data: String| Ok(data));
+    let addr = common::serve(&core, ServerBuilder::new(router));
+    let client = core.run(ClientBuilder::new().capacity(1).connect(&addr, &handle)).unwrap();
+
+    // The second request waits in its `Response` for room in the queue.
+    let first = client.request("echo", &[Value::from("first")]);
+    let second = client.request("echo", &[Value::from("second")]);
+    drop(client);
+    let (first, second) = core.run(first.join(second)).unwrap();
+    assert_eq!(first, Ok(Value::from("first")));
+    assert_eq!(second, Ok(Value::from("second")));
+}
@@ -0,0 +1,113 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::broker::{Broker, Overflow};
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server;
+use futures::{Future, Stream};
+use rmpv::Value;
+use std::time::Duration;
+use tokio_core::reactor::{Core, Timeout};
+
+fn connect(core: &mut Core, broker: &Broker) -> Client {
+    let handle = core.handle();
+    let router = Router::new().method("add", |a: i64, b: i64| Ok(a + b));
+    let listener = server::serve(&"127.0.0.1:0".parse().unwrap(), broker.handler(router), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+    core.run(Client::connect(&addr, &handle)).unwrap()
+}
+
+fn subscribe(core: &mut Core, client: &Client, topic: &str) {
+    assert_eq!(core.run(client.request("subscribe", &[Value::from(topic)])).unwrap(), Ok(Value::Nil));
+}
+
+#[test]
+fn publishes_to_subscribed_connections() {
+    let mut core = Core::new().unwrap();
+    let broker = Broker::new(Overflow::Buffer);
+    let prices = connect(&mut core, &broker);
+    let news = connect(&mut core, &broker);
+    let prices_rx = prices.notifications();
+    let news_rx = news.notifications();
+    subscribe(&mut core, &prices, "prices");
+    subscribe(&mut core, &news, "prices");
+    subscribe(&mut core, &news, "news");
+    assert_eq!(broker.subscribers("prices"), 2);
+
+    // The other methods are passed to the wrapped handler.
+    let sum = core.run(prices.request("add", &[Value::from(1), Value::from(2)])).unwrap();
+    assert_eq!(sum, Ok(Value::from(3)));
+    assert!(core.run(prices.request("subscribe", &[])).unwrap().is_err());
+
+    assert_eq!(broker.publish("prices", &[Value::from(42)]), 2);
+    assert_eq!(core.run(news.request("unsubscribe", &[Value::from("prices")])).unwrap(), Ok(Value::Nil));
+    assert_eq!(broker.publish("prices", &[Value::from(43)]), 1);
+    assert_eq!(broker.publish("news", &[Value::from("hello")]), 1);
+    assert_eq!(broker.publish("weather", &[]), 0);
+
+    let received = core.run(prices_rx.take(2).collect()).unwrap();
+    assert_eq!(received[0].method, "prices");
+    assert_eq!(received[0].params, vec![Value::from(42)]);
+    assert_eq!(received[1].params, vec![Value::from(43)]);
+    let received = core.run(news_rx.take(2).collect()).unwrap();
+    assert_eq!(received[0].params, vec![Value::from(42)]);
+    assert_eq!(received[1].method, "news");
+}
+
+#[test]
+fn skips_slow_subscribers() {
+    let mut core = Core::new().unwrap();
+    let broker = Broker::new(Overflow::Skip(2));
+    let client = connect(&mut core, &broker);
+    let notifications = client.notifications();
+    subscribe(&mut core, &client, "prices");
+
+    // The server does not write the notifications until the event loop runs again.
+    assert_eq!(broker.publish("prices", &[Value::from(1)]), 1);
+    assert_eq!(broker.publish("prices", &[Value::from(2)]), 1);
+    assert_eq!(broker.publish("prices", &[Value::from(3)]), 0);
+    assert_eq!(broker.subscribers("prices"), 1);
+
+    let received = core.run(notifications.take(2).collect()).unwrap();
+    assert_eq!(received[1].params, vec![Value::from(2)]);
+    assert_eq!(broker.publish("prices", &[Value::from(4)]), 1);
+}
+
+#[test]
+fn unsubscribes_slow_subscribers() {
+    let mut core = Core::new().unwrap();
+    let broker = Broker::new(Overflow::Unsubscribe(2));
+    let client = connect(&mut core, &broker);
+    subscribe(&mut core, &client, "prices");
+    subscribe(&mut core, &client, "news");
+
+    assert_eq!(broker.publish("prices", &[Value::from(1)]), 1);
+    assert_eq!(broker.publish("news", &[Value::from(2)]), 1);
+    assert_eq!(broker.publish("prices", &[Value::from(3)]), 0);
+    assert_eq!(broker.subscribers("prices"), 0);
+    assert_eq!(broker.subscribers("news"), 0);
+}
+
+#[test]
+fn unsubscribes_closed_connections() {
+    let mut core = Core::new().unwrap();
+    let broker = Broker::new(Overflow::Buffer);
+    let client = connect(&mut core, &broker);
+    subscribe(&mut core, &client, "prices");
+    subscribe(&mut core, &client, "news");
+    assert_eq!(broker.subscribers("prices"), 1);
+
+    // The connection is unsubscribed when it closes, without publishing to its topics.
+    drop(client);
+    for _ in 0..100 {
+        if broker.subscribers("prices") == 0 && broker.subscribers("news") == 0 {
+            return;
+        }
+        core.run(Timeout::new(Duration::from_millis(5), &core.handle()).unwrap()).unwrap();
+    }
+    panic!("The closed connection is still subscribed");
+}
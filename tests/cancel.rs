@@ -0,0 +1,32 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+mod common;
+
+use common::SleepHandler;
+use framed_msgpack_rpc::client::ClientBuilder;
+use framed_msgpack_rpc::server::ServerBuilder;
+use futures::Stream;
+use rmpv::Value;
+use tokio_core::reactor::Core;
+
+#[test]
+fn dropped_response_cancels_request() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let (handler, notifications_rx) = SleepHandler::forwarding();
+    let addr = common::serve(&core, ServerBuilder::new(handler));
+    let connection = ClientBuilder::new().cancel_notification("cancel").connect(&addr, &handle);
+    let client = core.run(connection).unwrap();
+
+    drop(client.request("sleep", &[Value::from(200)]));
+    let (cancel, _) = core.run(notifications_rx.into_future()).map_err(|_| ()).unwrap();
+    assert_eq!(cancel, Some(("cancel".to_owned(), vec![Value::from(1)])));
+
+    // The late response to the cancelled request is discarded without disturbing the client.
+    let response = core.run(client.request("sleep", &[Value::from(300)])).unwrap();
+    assert_eq!(response, Ok(Value::from(300)));
+    assert_eq!(client.discarded_responses(), 1);
+}
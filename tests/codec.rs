@@ -0,0 +1,235 @@
+extern crate bytes;
+extern crate framed_msgpack_rpc;
+extern crate rmpv;
+extern crate tokio_io;
+
+use bytes::BytesMut;
+use framed_msgpack_rpc::{Codec, Endianness, Error, LengthPrefix, UnframedCodec};
+use framed_msgpack_rpc::message::{Message, Notification, Request, Response};
+use rmpv::Value;
+use std::io;
+use tokio_io::codec::{Decoder, Encoder};
+
+fn messages() -> Vec<Message> {
+    vec![
+        Message::Request(Request {
+            id: 1,
+            method: "sayHello".to_owned(),
+            params: vec![Value::from("World")],
+        }),
+        Message::Response(Response {
+            id: 1,
+            result: Ok(Value::from("Hello World!")),
+        }),
+        Message::Response(Response {
+            id: 2,
+            result: Err(Value::from("Unknown method 'sayGoodbye'")),
+        }),
+        Message::Notification(Notification {
+            method: "update".to_owned(),
+            params: vec![Value::from(42), Value::Nil],
+        }),
+    ]
+}
+
+fn payload_len(msg: &Message) -> usize {
+    let mut buf = Vec::new();
+    rmpv::encode::write_value(&mut buf, &msg.clone().to_value()).unwrap();
+    buf.len()
+}
+
+fn round_trip(codec: Codec, expected_prefix: fn(usize) -> Vec<u8>) {
+    for msg in messages() {
+        let mut encoder = codec;
+        let mut buf = BytesMut::new();
+        encoder.encode(msg.clone(), &mut buf).unwrap();
+        let expected = expected_prefix(payload_len(&msg));
+        assert_eq!(&buf[..expected.len()], &expected[..]);
+        assert_eq!(buf.len(), expected.len() + payload_len(&msg));
+
+        let mut decoder = codec;
+        let mut partial = BytesMut::from(&buf[..buf.len() - 1]);
+        assert_eq!(decoder.decode(&mut partial).unwrap(), None);
+        let decoded = decoder.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(Message::from_value(decoded.to_value()).unwrap(), msg);
+        assert!(buf.is_empty());
+    }
+}
+
+#[test]
+fn default_is_u32_big_endian() {
+    round_trip(Codec::new(), |len| vec![0, 0, 0, len as u8]);
+}
+
+#[test]
+fn u16_big_endian() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::U16(Endianness::Big)).build();
+    round_trip(codec, |len| vec![0, len as u8]);
+}
+
+#[test]
+fn u16_little_endian() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::U16(Endianness::Little)).build();
+    round_trip(codec, |len| vec![len as u8, 0]);
+}
+
+#[test]
+fn u32_little_endian() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::U32(Endianness::Little)).build();
+    round_trip(codec, |len| vec![len as u8, 0, 0, 0]);
+}
+
+#[test]
+fn u64_big_endian() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::U64(Endianness::Big)).build();
+    round_trip(codec, |len| vec![0, 0, 0, 0, 0, 0, 0, len as u8]);
+}
+
+#[test]
+fn u64_little_endian() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::U64(Endianness::Little)).build();
+    round_trip(codec, |len| vec![len as u8, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn varint() {
+    let codec = Codec::builder().length_prefix(LengthPrefix::Varint).build();
+    round_trip(codec, |len| vec![len as u8]);
+}
+
+#[test]
+fn prefix_counts_itself() {
+    let codec = Codec::builder()
+        .length_prefix(LengthPrefix::U16(Endianness::Big))
+        .prefix_counts_itself(true)
+        .build();
+    round_trip(codec, |len| vec![0, len as u8 + 2]);
+}
+
+#[test]
+fn varint_prefix_counts_itself() {
+    let codec = Codec::builder()
+        .length_prefix(LengthPrefix::Varint)
+        .prefix_counts_itself(true)
+        .build();
+    round_trip(codec, |len| vec![len as u8 + 1]);
+}
+
+#[test]
+fn multi_byte_varint() {
+    let msg = Message::Notification(Notification {
+        method: "blob".to_owned(),
+        params: vec![Value::from(vec![0u8; 300])],
+    });
+    let mut codec = Codec::builder().length_prefix(LengthPrefix::Varint).build();
+    let mut buf = BytesMut::new();
+    codec.encode(msg.clone(), &mut buf).unwrap();
+    let len = payload_len(&msg);
+    assert_eq!(&buf[..2], &[(len as u8 & 0x7f) | 0x80, (len >> 7) as u8][..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(msg));
+}
+
+#[test]
+fn oversized_frame_is_rejected() {
+    let mut codec = Codec::builder().max_frame_len(16).build();
+    let mut buf = BytesMut::from(&[0xff, 0xff, 0xff, 0xff][..]);
+    let err = codec.decode(&mut buf).unwrap_err();
+    match err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+        Some(&Error::FrameTooLarge { len, max }) => {
+            assert_eq!(len, 0xffff_ffff);
+            assert_eq!(max, 16);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn frame_over_max_is_invalid_data() {
+    let mut codec = Codec::builder().max_frame_len(16).build();
+    let mut buf = BytesMut::from(&[0, 0, 0, 16][..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    let mut buf = BytesMut::from(&[0, 0, 0, 17][..]);
+    let err = codec.decode(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+        Some(&Error::FrameTooLarge { len: 17, max: 16 }) => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn length_too_large_for_prefix() {
+    let msg = Message::Notification(Notification {
+        method: "blob".to_owned(),
+        params: vec![Value::from(vec![0u8; 70000])],
+    });
+    let mut codec = Codec::builder().length_prefix(LengthPrefix::U16(Endianness::Big)).build();
+    let mut buf = BytesMut::new();
+    assert!(codec.encode(msg, &mut buf).is_err());
+}
+
+#[test]
+fn unframed_round_trip() {
+    let mut codec = UnframedCodec::new();
+    let mut buf = BytesMut::new();
+    for msg in messages() {
+        codec.encode(msg, &mut buf).unwrap();
+    }
+    let total = buf.len();
+    let mut partial = BytesMut::from(&buf[..total - 1]);
+    let mut decoded = Vec::new();
+    while let Some(msg) = codec.decode(&mut partial).unwrap() {
+        decoded.push(msg);
+    }
+    assert_eq!(decoded.len(), messages().len() - 1);
+    assert!(!partial.is_empty());
+    partial.extend_from_slice(&buf[total - 1..]);
+    decoded.push(codec.decode(&mut partial).unwrap().unwrap());
+    assert_eq!(decoded, messages());
+    assert!(partial.is_empty());
+}
+
+#[test]
+fn unframed_matches_bare_msgpack() {
+    for msg in messages() {
+        let mut buf = BytesMut::new();
+        UnframedCodec::new().encode(msg.clone(), &mut buf).unwrap();
+        let mut expected = Vec::new();
+        rmpv::encode::write_value(&mut expected, &msg.to_value()).unwrap();
+        assert_eq!(&buf[..], &expected[..]);
+    }
+}
+
+#[test]
+fn unframed_decodes_byte_by_byte() {
+    let mut codec = UnframedCodec::new().max_frame_len(1024);
+    let mut buf = BytesMut::new();
+    for msg in messages() {
+        codec.encode(msg, &mut buf).unwrap();
+    }
+    let mut partial = BytesMut::new();
+    let mut decoded = Vec::new();
+    for byte in buf.iter() {
+        partial.extend_from_slice(&[*byte]);
+        if let Some(msg) = codec.decode(&mut partial).unwrap() {
+            decoded.push(msg);
+        }
+    }
+    assert_eq!(decoded, messages());
+    assert!(partial.is_empty());
+}
+
+#[test]
+fn unframed_oversized_value_is_rejected() {
+    // A request whose method is announced as a 4 GiB string.
+    let mut codec = UnframedCodec::new().max_frame_len(1024);
+    let mut buf = BytesMut::from(&[0x94, 0x00, 0x01, 0xdb, 0xff, 0xff, 0xff, 0xff, b'a'][..]);
+    let err = codec.decode(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+        Some(&Error::FrameTooLarge { max: 1024, .. }) => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
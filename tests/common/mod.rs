@@ -0,0 +1,73 @@
+//! Fixtures shared by the integration tests.
+
+// Each test only uses some of the fixtures.
+#![allow(dead_code)]
+
+use framed_msgpack_rpc::server::{Handler, ServerBuilder};
+use futures::{future, BoxFuture, Future};
+use futures::sync::{mpsc, oneshot};
+use rmpv::Value;
+use std::io;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+/// Completes with the `value` after the `delay`, which elapses on another thread.
+pub fn delayed<T: Send + 'static>(delay: Duration, value: T) -> BoxFuture<T, io::Error> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        let _ = tx.send(value);
+    });
+    Box::new(rx.map_err(|_| io::Error::new(io::ErrorKind::Other, "canceled")))
+}
+
+/// Binds the server to a free local port and serves it on the event loop of the `core`.
+///
+/// Returns the address of the server.
+pub fn serve<H: Handler + 'static>(core: &Core, builder: ServerBuilder<H>) -> SocketAddr {
+    let handle = core.handle();
+    let listener = builder.bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+    addr
+}
+
+/// Responds to requests after the number of milliseconds given as the parameter, and forwards
+/// notifications to the test, if any.
+#[derive(Clone)]
+pub struct SleepHandler {
+    notifications_tx: Option<mpsc::UnboundedSender<(String, Vec<Value>)>>,
+}
+
+impl SleepHandler {
+    /// Creates a `SleepHandler` that drops notifications.
+    pub fn new() -> Self {
+        SleepHandler { notifications_tx: None }
+    }
+
+    /// Creates a `SleepHandler` that forwards notifications to the returned stream.
+    pub fn forwarding() -> (Self, mpsc::UnboundedReceiver<(String, Vec<Value>)>) {
+        let (tx, rx) = mpsc::unbounded();
+        (SleepHandler { notifications_tx: Some(tx) }, rx)
+    }
+}
+
+impl Handler for SleepHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, _method: &str, params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        let ms = params[0].as_u64().unwrap();
+        delayed(Duration::from_millis(ms), Ok(Value::from(ms)))
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &[Value]) -> BoxFuture<(), Self::Error> {
+        if let Some(ref tx) = self.notifications_tx {
+            let _ = mpsc::UnboundedSender::send(tx, (method.to_owned(), params.to_vec()));
+        }
+        Box::new(future::ok(()))
+    }
+}
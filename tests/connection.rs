@@ -0,0 +1,40 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+extern crate tokio_io;
+
+use framed_msgpack_rpc::client::{Client, RequestError};
+use futures::{Future, Stream};
+use rmpv::Value;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Core;
+
+#[test]
+fn pending_requests_fail_when_server_closes() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Accept a single connection and close it as soon as the first request has been read.
+    let server = listener.incoming()
+        .into_future()
+        .map_err(|(e, _)| e)
+        .and_then(|(stream, _)| {
+            let (stream, _) = stream.unwrap();
+            tokio_io::io::read(stream, vec![0; 64])
+        })
+        .map(|_| ())
+        .map_err(|e| panic!("{}", e));
+    handle.spawn(server);
+
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+    match core.run(client.request("sayHello", &[Value::from("World")])) {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+    match core.run(client.request("sayHello", &[Value::from("again")])) {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+}
@@ -0,0 +1,107 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+extern crate tokio_io;
+
+mod common;
+
+use common::SleepHandler;
+use framed_msgpack_rpc::Codec;
+use framed_msgpack_rpc::client::{Client, RequestError};
+use framed_msgpack_rpc::message::{Message, Request, Response};
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::{self, ServerBuilder};
+use futures::{Future, Sink, Stream};
+use rmpv::Value;
+use std::io;
+use std::net::Shutdown;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Core;
+use tokio_io::AsyncRead;
+
+/// A frame with a valid length prefix around a byte that is not valid MessagePack.
+const INVALID_MSGPACK: &'static [u8] = &[0, 0, 0, 1, 0xc1];
+
+/// A frame with a valid length prefix around a MessagePack integer instead of an array.
+const NOT_AN_ARRAY: &'static [u8] = &[0, 0, 0, 1, 0x01];
+
+#[test]
+fn server_drops_connection_on_corrupt_frame() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let router = Router::new().method("sayHello", |name: String| Ok(format!("Hello {}!", name)));
+    let listener = server::serve(&"127.0.0.1:0".parse().unwrap(), router, &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = listener.connections();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+
+    for frame in &[INVALID_MSGPACK, NOT_AN_ARRAY] {
+        let closed = TcpStream::connect(&addr, &handle)
+            .and_then(move |stream| tokio_io::io::write_all(stream, *frame))
+            .and_then(|(stream, _)| tokio_io::io::read_to_end(stream, Vec::new()));
+        let (_, received) = core.run(closed).unwrap();
+        assert!(received.is_empty());
+    }
+
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+    let response = core.run(client.request("sayHello", &[Value::from("World")])).unwrap();
+    assert_eq!(response, Ok(Value::from("Hello World!")));
+    assert_eq!(connections.count(), 1);
+}
+
+#[test]
+fn client_fails_pending_requests_on_corrupt_frame() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Answer the first request with a corrupt frame and keep the connection open.
+    let server = listener.incoming()
+        .into_future()
+        .map_err(|(e, _)| e)
+        .and_then(|(stream, _)| {
+            let (stream, _) = stream.unwrap();
+            tokio_io::io::read(stream, vec![0; 64])
+        })
+        .and_then(|(stream, _, _)| tokio_io::io::write_all(stream, INVALID_MSGPACK))
+        .and_then(|(stream, _)| tokio_io::io::read_to_end(stream, Vec::new()))
+        .map(|_| ())
+        .map_err(|e| panic!("{}", e));
+    handle.spawn(server);
+
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+    match core.run(client.request("sayHello", &[Value::from("World")])) {
+        Err(RequestError::Io(ref e)) if e.kind() == io::ErrorKind::InvalidData => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+    match core.run(client.notify("update", &[])) {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+}
+
+#[test]
+fn server_responds_after_the_client_closes_its_write_half() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let addr = common::serve(&core, ServerBuilder::new(SleepHandler::new()));
+
+    let request = Message::Request(Request {
+        id: 1,
+        method: "sleep".to_owned(),
+        params: vec![Value::from(50)],
+    });
+    let responses = TcpStream::connect(&addr, &handle)
+        .and_then(|stream| stream.framed(Codec::new()).send(request))
+        .and_then(|framed| {
+            framed.get_ref().shutdown(Shutdown::Write)?;
+            Ok(framed)
+        })
+        .and_then(|framed| framed.collect());
+    let responses = core.run(responses).unwrap();
+    assert_eq!(responses, vec![Message::Response(Response {
+        id: 1,
+        result: Ok(Value::from(50)),
+    })]);
+}
@@ -0,0 +1,61 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::server::{Handler, ServerBuilder};
+use futures::{future, BoxFuture, Future};
+use rmpv::Value;
+use std::io;
+use tokio_core::reactor::Core;
+
+/// Fails the "fail" method and every notification, and answers "ping".
+#[derive(Clone)]
+struct FailingHandler;
+
+impl Handler for FailingHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, _params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        match method {
+            "fail" => Box::new(future::err(io::Error::new(io::ErrorKind::Other, "boom"))),
+            _ => Box::new(future::ok(Ok(Value::from("pong")))),
+        }
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::err(io::Error::new(io::ErrorKind::Other, "boom")))
+    }
+}
+
+fn connect(core: &mut Core, builder: ServerBuilder<FailingHandler>) -> Client {
+    let handle = core.handle();
+    let listener = builder.bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+    core.run(Client::connect(&addr, &handle)).unwrap()
+}
+
+#[test]
+fn failed_handler_produces_error_response() {
+    let mut core = Core::new().unwrap();
+    let client = connect(&mut core, ServerBuilder::new(FailingHandler));
+
+    let expected = Value::Map(vec![(Value::from("code"), Value::from(-32603)),
+                                   (Value::from("message"), Value::from("boom"))]);
+    assert_eq!(core.run(client.request("fail", &[])).unwrap(), Err(expected));
+    core.run(client.notify("fail", &[])).unwrap();
+    assert_eq!(core.run(client.request("ping", &[])).unwrap(), Ok(Value::from("pong")));
+}
+
+#[test]
+fn error_formatter_can_be_replaced() {
+    let mut core = Core::new().unwrap();
+    let builder = ServerBuilder::new(FailingHandler).error_formatter(|e| Value::from(format!("failed: {}", e)));
+    let client = connect(&mut core, builder);
+
+    assert_eq!(core.run(client.request("fail", &[])).unwrap(), Err(Value::from("failed: boom")));
+}
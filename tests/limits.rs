@@ -0,0 +1,169 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+extern crate tokio_io;
+
+mod common;
+
+use framed_msgpack_rpc::Codec;
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::message::{Message, Request, RpcError};
+use framed_msgpack_rpc::server::{ConcurrencyLimit, Handler, Overload, ServerBuilder};
+use futures::{future, BoxFuture, Future, Sink, Stream};
+use rmpv::Value;
+use std::convert::TryFrom;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Core, Timeout};
+use tokio_io::AsyncRead;
+
+/// Responds to each request after 50 milliseconds, recording the number of requests handled at
+/// once and the order in which their methods were handled.
+#[derive(Clone)]
+struct SlowHandler {
+    active: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+    started: Arc<Mutex<Vec<String>>>,
+}
+
+impl SlowHandler {
+    fn new() -> Self {
+        SlowHandler {
+            active: Arc::new(AtomicUsize::new(0)),
+            peak: Arc::new(AtomicUsize::new(0)),
+            started: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Handler for SlowHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, _params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        self.started.lock().unwrap().push(method.to_owned());
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        if active > self.peak.load(Ordering::SeqCst) {
+            self.peak.store(active, Ordering::SeqCst);
+        }
+        let done = self.active.clone();
+        Box::new(common::delayed(Duration::from_millis(50), Ok(Value::from("done"))).map(move |response| {
+            done.fetch_sub(1, Ordering::SeqCst);
+            response
+        }))
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+}
+
+fn connect(core: &mut Core, builder: ServerBuilder<SlowHandler>, clients: usize) -> Vec<Client> {
+    let handle = core.handle();
+    let addr = common::serve(core, builder);
+    (0..clients).map(|_| core.run(Client::connect(&addr, &handle)).unwrap()).collect()
+}
+
+/// Sends `count` requests on each client at once, and waits for all the responses.
+fn requests(core: &mut Core, clients: &[Client], count: usize) -> Vec<Result<Value, Value>> {
+    let responses: Vec<_> = clients.iter()
+        .flat_map(|client| (0..count).map(move |_| client.request("sleep", &[])))
+        .collect();
+    core.run(future::join_all(responses)).unwrap()
+}
+
+#[test]
+fn holds_requests_beyond_max_in_flight() {
+    let mut core = Core::new().unwrap();
+    let handler = SlowHandler::new();
+    let peak = handler.peak.clone();
+    let clients = connect(&mut core, ServerBuilder::new(handler).max_in_flight(2), 1);
+
+    let responses = requests(&mut core, &clients, 6);
+    assert!(responses.iter().all(|r| *r == Ok(Value::from("done"))));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn rejects_requests_beyond_max_in_flight() {
+    let mut core = Core::new().unwrap();
+    let builder = ServerBuilder::new(SlowHandler::new()).max_in_flight(1).overload(Overload::Reject);
+    let clients = connect(&mut core, builder, 1);
+
+    let responses = requests(&mut core, &clients, 3);
+    assert_eq!(responses[0], Ok(Value::from("done")));
+    for response in &responses[1..] {
+        let error = RpcError::try_from(response.clone().unwrap_err()).unwrap();
+        assert_eq!(error.code, RpcError::OVERLOADED);
+    }
+}
+
+#[test]
+fn global_limit_is_shared_between_connections() {
+    let mut core = Core::new().unwrap();
+    let handler = SlowHandler::new();
+    let peak = handler.peak.clone();
+    let limit = ConcurrencyLimit::new(1);
+    let clients = connect(&mut core, ServerBuilder::new(handler).global_limit(&limit), 2);
+
+    let responses = requests(&mut core, &clients, 2);
+    assert!(responses.iter().all(|r| *r == Ok(Value::from("done"))));
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn duplicate_ids_do_not_bypass_global_limit() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let handler = SlowHandler::new();
+    let peak = handler.peak.clone();
+    let limit = ConcurrencyLimit::new(2);
+    let addr = common::serve(&core, ServerBuilder::new(handler).global_limit(&limit));
+
+    // Two requests with the same ID take both permits.
+    let request = || {
+        Message::Request(Request {
+            id: 7,
+            method: "sleep".to_owned(),
+            params: Vec::new(),
+        })
+    };
+    let stream = core.run(TcpStream::connect(&addr, &handle)).unwrap();
+    let sent = stream.framed(Codec::new()).send(request()).and_then(move |framed| framed.send(request()));
+    let framed = core.run(sent).unwrap();
+    core.run(Timeout::new(Duration::from_millis(10), &handle).unwrap()).unwrap();
+
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+    let responses = requests(&mut core, &[client], 2);
+    assert!(responses.iter().all(|r| *r == Ok(Value::from("done"))));
+    let duplicates = core.run(framed.take(2).collect()).unwrap();
+    assert_eq!(duplicates.len(), 2);
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn waiting_connections_are_not_overtaken() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let handler = SlowHandler::new();
+    let started = handler.started.clone();
+    let limit = ConcurrencyLimit::new(1);
+    let clients = connect(&mut core, ServerBuilder::new(handler).global_limit(&limit), 2);
+    let pause = |core: &mut Core| core.run(Timeout::new(Duration::from_millis(10), &handle).unwrap()).unwrap();
+
+    // The second connection waits for the permit before the first one sends its next request, so
+    // it is handled first, even though the permit is released by the first connection.
+    let first = clients[0].request("first", &[]);
+    pause(&mut core);
+    let second = clients[1].request("second", &[]);
+    pause(&mut core);
+    let third = clients[0].request("third", &[]);
+    pause(&mut core);
+    core.run(future::join_all(vec![first, second, third])).unwrap();
+    assert_eq!(*started.lock().unwrap(), vec!["first", "second", "third"]);
+}
@@ -0,0 +1,48 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server;
+use futures::Future;
+use futures::sync::oneshot;
+use rmpv::Value;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+#[test]
+fn serves_connections_until_shutdown() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let router = Router::new().method("sayHello", |name: String| Ok(format!("Hello {}!", name)));
+    let listener = server::serve(&"127.0.0.1:0".parse().unwrap(), router, &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = listener.shutdown_handle();
+    let connections = listener.connections();
+    let (done_tx, done_rx) = oneshot::channel();
+    handle.spawn(listener.then(move |result| {
+        let _ = done_tx.send(result.is_ok());
+        Ok(())
+    }));
+
+    let hello = |client: Client| {
+        client.request("sayHello", &[Value::from("World")]).map(move |r| (client, r)).map_err(|_| ())
+    };
+    let clients = Client::connect(&addr, &handle)
+        .join(Client::connect(&addr, &handle))
+        .map_err(|_| ())
+        .and_then(move |(a, b)| hello(a).join(hello(b)));
+    let ((a, ra), (_b, rb)) = core.run(clients).unwrap();
+    assert_eq!(ra, Ok(Value::from("Hello World!")));
+    assert_eq!(rb, Ok(Value::from("Hello World!")));
+    assert_eq!(connections.count(), 2);
+
+    shutdown.shutdown(Duration::from_secs(1));
+    // The server stops reading and closes the connections, which ends the outstanding requests.
+    let closed = core.run(a.request("sayHello", &[Value::from("again")]).then(Ok::<_, ()>)).unwrap();
+    assert!(closed.is_err());
+    assert!(core.run(done_rx).unwrap());
+    assert_eq!(connections.count(), 0);
+}
@@ -0,0 +1,115 @@
+extern crate framed_msgpack_rpc;
+extern crate rmpv;
+
+use framed_msgpack_rpc::Error;
+use framed_msgpack_rpc::message::{Message, RpcError};
+use rmpv::Value;
+use std::convert::TryFrom;
+
+fn parse(values: Vec<Value>) -> Result<Message, Error> {
+    Message::from_value(Value::Array(values))
+}
+
+#[test]
+fn not_an_array() {
+    match Message::from_value(Value::from("hello")) {
+        Err(Error::NotAnArray) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn wrong_arity() {
+    match parse(vec![Value::from(0), Value::from(1), Value::from("sayHello")]) {
+        Err(Error::WrongArity { expected: 4, got: 3 }) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match parse(vec![Value::from(2), Value::from("update")]) {
+        Err(Error::WrongArity { expected: 3, got: 2 }) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn unknown_message_type() {
+    match parse(vec![Value::from(3), Value::from(1), Value::from("sayHello"), Value::Array(vec![])]) {
+        Err(Error::UnknownMessageType(3)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match parse(vec![Value::from("0"), Value::from(1), Value::from("sayHello"), Value::Array(vec![])]) {
+        Err(Error::BadMessageType) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn bad_fields() {
+    match parse(vec![Value::from(0), Value::from("1"), Value::from("sayHello"), Value::Array(vec![])]) {
+        Err(Error::BadId) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match parse(vec![Value::from(0), Value::from(1), Value::from(2), Value::Array(vec![])]) {
+        Err(Error::BadMethod) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match parse(vec![Value::from(2), Value::from("update"), Value::Nil]) {
+        Err(Error::BadParams) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn id_out_of_range() {
+    let request = || vec![Value::from(0), Value::from(0x1_0000_0005u64), Value::from("sayHello"), Value::Array(vec![])];
+    match parse(request()) {
+        Err(Error::IdOutOfRange(0x1_0000_0005)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match Message::from_value_lenient(Value::Array(request())) {
+        Ok(Message::Request(ref r)) if r.id == 0x1_0000_0005 => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    let response = vec![Value::from(1), Value::from(0x1_0000_0005u64), Value::Nil, Value::Nil];
+    match parse(response) {
+        Err(Error::IdOutOfRange(0x1_0000_0005)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match parse(vec![Value::from(0), Value::from(0xffff_ffffu64), Value::from("sayHello"), Value::Array(vec![])]) {
+        Ok(Message::Request(ref r)) if r.id == 0xffff_ffff => {}
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn deserialize_params() {
+    let params = Value::Array(vec![Value::from(2), Value::from("two")]);
+    let request = match parse(vec![Value::from(0), Value::from(1), Value::from("add"), params]) {
+        Ok(Message::Request(r)) => r,
+        r => panic!("Unexpected result: {:?}", r),
+    };
+    let (n, s): (u32, String) = request.deserialize_params().unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(s, "two");
+    assert!(request.deserialize_params::<(String, u32)>().is_err());
+}
+
+#[test]
+fn rpc_error_round_trip() {
+    let error = RpcError::invalid_params("expected a topic").with_data(vec![Value::from(1)]);
+    let value = Value::from(error.clone());
+    assert_eq!(value,
+               Value::Map(vec![(Value::from("code"), Value::from(-32602)),
+                               (Value::from("message"), Value::from("expected a topic")),
+                               (Value::from("data"), Value::Array(vec![Value::from(1)]))]));
+    assert_eq!(RpcError::try_from(value).unwrap(), error);
+
+    let without_data = RpcError::new(42, "custom");
+    assert_eq!(RpcError::try_from(Value::from(without_data.clone())).unwrap(), without_data);
+}
+
+#[test]
+fn rpc_error_rejects_other_values() {
+    assert_eq!(RpcError::try_from(Value::from("failed")), Err(Value::from("failed")));
+    let no_code = Value::Map(vec![(Value::from("message"), Value::from("failed"))]);
+    assert_eq!(RpcError::try_from(no_code.clone()), Err(no_code));
+}
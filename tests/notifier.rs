@@ -0,0 +1,111 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::server::{self, Handler, Notifier, Server};
+use futures::{future, BoxFuture, Future, Stream};
+use futures::future::Either;
+use rmpv::Value;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Timeout};
+
+/// Pushes a "changed" notification with the parameters of each "publish" request.
+#[derive(Clone)]
+struct FeedHandler {
+    notifier: Arc<Mutex<Option<Notifier>>>,
+}
+
+impl Handler for FeedHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, _method: &str, params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        let notifier = self.notifier.lock().unwrap();
+        Box::new(future::result(notifier.as_ref().unwrap().notify("changed", params).map(|_| Ok(Value::Nil))))
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+
+    fn connected(&mut self, notifier: Notifier) {
+        *self.notifier.lock().unwrap() = Some(notifier);
+    }
+}
+
+#[test]
+fn pushes_notifications_to_client() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let handler = FeedHandler { notifier: Arc::new(Mutex::new(None)) };
+    let listener = server::serve(&"127.0.0.1:0".parse().unwrap(), handler, &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+    let notifications = client.notifications();
+    core.run(client.request("publish", &[Value::from(1)])).unwrap().unwrap();
+    core.run(client.request("publish", &[Value::from(2)])).unwrap().unwrap();
+    let received = core.run(notifications.take(2).collect()).unwrap();
+    let params: Vec<_> = received.into_iter()
+        .map(|n| {
+            assert_eq!(n.method, "changed");
+            n.params
+        })
+        .collect();
+    assert_eq!(params, vec![vec![Value::from(1)], vec![Value::from(2)]]);
+}
+
+#[test]
+fn notifier_fails_once_server_stops() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = listener.incoming()
+        .into_future()
+        .map(|(stream, _)| stream.unwrap().0)
+        .map_err(|(e, _)| e);
+    let (client, stream) = core.run(Client::connect(&addr, &handle).join(accepted)).unwrap();
+    let notifications = client.notifications();
+
+    let handler = FeedHandler { notifier: Arc::new(Mutex::new(None)) };
+    let server = Server::new(handler, stream);
+    let notifier = server.notifier();
+    assert!(notifier.notify("changed", &[]).is_ok());
+    // Dropping the server closes the connection before the notification is written.
+    drop(server);
+    match notifier.notify("changed", &[]) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+    assert!(core.run(notifications.collect()).unwrap().is_empty());
+}
+
+#[test]
+fn notifications_end_once_the_connection_has_ended() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = listener.incoming()
+        .into_future()
+        .map(|(stream, _)| stream.unwrap().0)
+        .map_err(|(e, _)| e);
+    let (client, stream) = core.run(Client::connect(&addr, &handle).join(accepted)).unwrap();
+    drop(stream);
+    core.run(client.closed()).unwrap();
+
+    // A stream created after the connection has ended is already ended.
+    let guard = Timeout::new(Duration::from_secs(5), &handle).unwrap();
+    match core.run(client.notifications().collect().select2(guard)) {
+        Ok(Either::A((received, _))) => assert!(received.is_empty()),
+        _ => panic!("The stream of notifications did not end"),
+    }
+}
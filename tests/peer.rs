@@ -0,0 +1,118 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::{Client, Peer};
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::{Handler, Notifier};
+use futures::{future, BoxFuture, Future, Stream};
+use futures::future::Either;
+use rmpv::Value;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Core, Timeout};
+
+/// Pushes a "pushed" notification with the parameters of each "push" request, and records when
+/// the connection is no longer served.
+#[derive(Clone)]
+struct PushHandler {
+    disconnected: Arc<AtomicBool>,
+    notifier: Arc<Mutex<Option<Notifier>>>,
+}
+
+impl Handler for PushHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, _method: &str, params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        let notifier = self.notifier.lock().unwrap();
+        Box::new(future::result(notifier.as_ref().unwrap().notify("pushed", params).map(|_| Ok(Value::Nil))))
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+
+    fn connected(&mut self, notifier: Notifier) {
+        *self.notifier.lock().unwrap() = Some(notifier);
+    }
+
+    fn disconnected(&mut self) {
+        self.disconnected.store(true, Ordering::SeqCst);
+    }
+}
+
+fn connect(core: &mut Core) -> (TcpStream, TcpStream) {
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = listener.incoming()
+        .into_future()
+        .map(|(stream, _)| stream.unwrap().0)
+        .map_err(|(e, _)| e);
+    let connected = TcpStream::connect(&addr, &handle);
+    core.run(accepted.join(connected)).unwrap()
+}
+
+#[test]
+fn peers_call_each_other() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let (accepted, connected) = connect(&mut core);
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    let server_router = Router::new()
+        .method("add", |a: i64, b: i64| Ok(a + b))
+        .notification("log", move |msg: String| log.lock().unwrap().push(msg));
+    let client_router = Router::new().method("sayHello", |name: String| Ok(format!("Hello {}!", name)));
+    let server = Peer::new(server_router, accepted, &handle);
+    let client = Peer::new(client_router, connected, &handle);
+
+    let requests = server.request("sayHello", &[Value::from("World")])
+        .join(client.request("add", &[Value::from(2), Value::from(3)]));
+    let (hello, sum) = core.run(requests).unwrap();
+    assert_eq!(hello, Ok(Value::from("Hello World!")));
+    assert_eq!(sum, Ok(Value::from(5)));
+
+    core.run(client.notify("log", &[Value::from("hello")])).unwrap();
+    // The server answers the request only after it has handled the notification sent before it.
+    let sum = core.run(client.call::<_, i64>("add", (1, 1))).unwrap();
+    assert_eq!(sum, 2);
+    assert_eq!(*received.lock().unwrap(), vec!["hello".to_owned()]);
+}
+
+#[test]
+fn peer_serves_its_handler_until_every_handle_is_dropped() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let (accepted, connected) = connect(&mut core);
+    let handler = PushHandler {
+        disconnected: Arc::new(AtomicBool::new(false)),
+        notifier: Arc::new(Mutex::new(None)),
+    };
+    let disconnected = handler.disconnected.clone();
+    let peer = Peer::new(handler, accepted, &handle);
+    let client = Client::from_io(connected, &handle);
+    let notifications = client.notifications();
+
+    core.run(client.request("push", &[Value::from(1)])).unwrap().unwrap();
+    assert!(!disconnected.load(Ordering::SeqCst));
+    // Dropping the last handle closes the connection.
+    drop(peer);
+    let guard = Timeout::new(Duration::from_secs(5), &handle).unwrap();
+    match core.run(client.closed().select2(guard)) {
+        Ok(Either::A(_)) => {}
+        _ => panic!("The connection was not closed"),
+    }
+    assert!(disconnected.load(Ordering::SeqCst));
+    let received = core.run(notifications.collect()).unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].method, "pushed");
+    assert_eq!(received[0].params, vec![Value::from(1)]);
+}
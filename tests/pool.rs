@@ -0,0 +1,132 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::pool::{Pool, PoolBuilder, Strategy};
+use framed_msgpack_rpc::reconnect::ReconnectBuilder;
+use framed_msgpack_rpc::server::{Handler, Server, Shutdown};
+use futures::{future, BoxFuture, Future, Stream};
+use rmpv::Value;
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Timeout};
+
+/// Answers "whoami" with the index of the replica, and never answers "hang".
+#[derive(Clone)]
+struct Replica {
+    index: u64,
+}
+
+impl Handler for Replica {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, _params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        match method {
+            "whoami" => Box::new(future::ok(Ok(Value::from(self.index)))),
+            _ => Box::new(future::empty()),
+        }
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+}
+
+/// Starts replicas that each serve a single connection, until their `Shutdown` is triggered.
+fn replicas(core: &Core, count: u64) -> (Vec<SocketAddr>, Vec<Shutdown>) {
+    let handle = core.handle();
+    let mut addrs = Vec::new();
+    let mut shutdowns = Vec::new();
+    for index in 0..count {
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        addrs.push(listener.local_addr().unwrap());
+        let shutdown = Shutdown::new(&handle);
+        let server_shutdown = shutdown.clone();
+        let server = listener.incoming()
+            .into_future()
+            .map_err(|(e, _)| e)
+            .and_then(move |(stream, _)| {
+                let (stream, _) = stream.unwrap();
+                Server::new(Replica { index: index }, stream).with_shutdown(&server_shutdown)
+            });
+        handle.spawn(server.map_err(|_| ()));
+        shutdowns.push(shutdown);
+    }
+    (addrs, shutdowns)
+}
+
+fn pool(core: &Core, addrs: &[SocketAddr], strategy: Strategy) -> Pool {
+    let client = ReconnectBuilder::new().backoff(Duration::from_millis(10), Duration::from_millis(50));
+    PoolBuilder::new().client(client).strategy(strategy).connect(addrs, &core.handle())
+}
+
+/// Runs the event loop until the number of connected members is `count`.
+fn wait_connected(core: &mut Core, pool: &Pool, count: usize) {
+    while pool.connected() != count {
+        core.run(Timeout::new(Duration::from_millis(5), &core.handle()).unwrap()).unwrap();
+    }
+}
+
+fn whoami(core: &mut Core, pool: &Pool, key: Option<&str>) -> u64 {
+    let response = match key {
+        Some(key) => pool.request_with_key(key, "whoami", &[]),
+        None => pool.request("whoami", &[]),
+    };
+    core.run(response).unwrap().unwrap().as_u64().unwrap()
+}
+
+#[test]
+fn round_robin_skips_disconnected_members() {
+    let mut core = Core::new().unwrap();
+    let (addrs, shutdowns) = replicas(&core, 3);
+    let pool = pool(&core, &addrs, Strategy::RoundRobin);
+    assert_eq!(pool.members(), 3);
+    wait_connected(&mut core, &pool, 3);
+
+    let mut counts = [0; 3];
+    for _ in 0..6 {
+        counts[whoami(&mut core, &pool, None) as usize] += 1;
+    }
+    assert_eq!(counts, [2, 2, 2]);
+
+    // The replica does not accept another connection once it has shut down.
+    shutdowns[0].shutdown(Duration::from_millis(0));
+    wait_connected(&mut core, &pool, 2);
+    for _ in 0..4 {
+        assert!(whoami(&mut core, &pool, None) != 0);
+    }
+}
+
+#[test]
+fn least_outstanding_avoids_busy_members() {
+    let mut core = Core::new().unwrap();
+    let (addrs, _shutdowns) = replicas(&core, 3);
+    let pool = pool(&core, &addrs, Strategy::LeastOutstanding);
+    wait_connected(&mut core, &pool, 3);
+
+    let _hang = pool.request("hang", &[]);
+    let replicas: HashSet<u64> = (0..6).map(|_| whoami(&mut core, &pool, None)).collect();
+    assert_eq!(replicas.len(), 2);
+}
+
+#[test]
+fn consistent_hash_sticks_to_a_member() {
+    let mut core = Core::new().unwrap();
+    let (addrs, _shutdowns) = replicas(&core, 3);
+    let pool = pool(&core, &addrs, Strategy::ConsistentHash);
+    wait_connected(&mut core, &pool, 3);
+
+    let first = whoami(&mut core, &pool, Some("user-1"));
+    for _ in 0..4 {
+        assert_eq!(whoami(&mut core, &pool, Some("user-1")), first);
+    }
+    let keys = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+    let replicas: HashSet<u64> = keys.iter().map(|key| whoami(&mut core, &pool, Some(key))).collect();
+    assert!(replicas.len() > 1);
+}
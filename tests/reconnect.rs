@@ -0,0 +1,128 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+use framed_msgpack_rpc::client::RequestError;
+use framed_msgpack_rpc::reconnect::{Policy, ReconnectBuilder, State};
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::{Server, Shutdown};
+use futures::{Future, Stream};
+use rmpv::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle};
+
+fn router() -> Router {
+    Router::new().method("add", |a: i64, b: i64| Ok(a + b))
+}
+
+fn builder() -> ReconnectBuilder {
+    ReconnectBuilder::new().backoff(Duration::from_millis(10), Duration::from_millis(50))
+}
+
+/// Serves each connection accepted on the listener, shutting down the first one with the
+/// `shutdown` handle.
+fn serve(listener: TcpListener, shutdown: Shutdown, handle: &Handle) {
+    let handle2 = handle.clone();
+    let mut accepted = 0;
+    let server = listener.incoming().for_each(move |(stream, _)| {
+        accepted += 1;
+        let server = Server::new(router(), stream);
+        if accepted == 1 {
+            handle2.spawn(server.with_shutdown(&shutdown).map_err(|_| ()));
+        } else {
+            handle2.spawn(server.map_err(|_| ()));
+        }
+        Ok(())
+    });
+    handle.spawn(server.map_err(|e| panic!("{}", e)));
+}
+
+fn add(a: i64, b: i64) -> [Value; 2] {
+    [Value::from(a), Value::from(b)]
+}
+
+/// Returns an address that nothing listens on.
+fn unused_addr(handle: &Handle) -> SocketAddr {
+    TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), handle).unwrap().local_addr().unwrap()
+}
+
+#[test]
+fn reconnects_when_connection_is_lost() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = Shutdown::new(&handle);
+    serve(listener, shutdown.clone(), &handle);
+
+    // The first request is queued until the client has connected.
+    let client = builder().policy(Policy::Queue(1)).connect(&addr, &handle);
+    assert_eq!(core.run(client.request("add", &add(1, 2))).unwrap(), Ok(Value::from(3)));
+    assert_eq!(client.state(), State::Connected);
+
+    let changes = client.state_changes();
+    shutdown.shutdown(Duration::from_millis(0));
+    let changes = core.run(changes.take(3).collect()).unwrap();
+    assert_eq!(changes, vec![State::Disconnected, State::Connecting, State::Connected]);
+    assert_eq!(core.run(client.request("add", &add(2, 3))).unwrap(), Ok(Value::from(5)));
+}
+
+#[test]
+fn applies_policy_while_disconnected() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let addr = unused_addr(&handle);
+
+    let failing = builder().connect(&addr, &handle);
+    let queueing = builder().policy(Policy::Queue(1)).connect(&addr, &handle);
+    let disconnected = queueing.state_changes().filter(|state| *state == State::Disconnected);
+    core.run(disconnected.into_future().map(|_| ()).map_err(|_| ())).unwrap();
+
+    match core.run(failing.request("add", &add(1, 1))) {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+    let queued = queueing.request("add", &add(1, 2));
+    match core.run(queueing.request("add", &add(1, 3))) {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+
+    // The queued request is sent once the server is listening.
+    let listener = TcpListener::bind(&addr, &handle).unwrap();
+    serve(listener, Shutdown::new(&handle), &handle);
+    assert_eq!(core.run(queued).unwrap(), Ok(Value::from(3)));
+}
+
+#[test]
+fn abandoned_requests_are_not_sent() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let addr = unused_addr(&handle);
+
+    let client = builder().policy(Policy::Queue(1)).connect(&addr, &handle);
+    let disconnected = client.state_changes().filter(|state| *state == State::Disconnected);
+    core.run(disconnected.into_future().map(|_| ()).map_err(|_| ())).unwrap();
+
+    // The abandoned request makes room in the queue for the next one.
+    drop(client.request("count", &[]));
+    let kept = client.request("count", &[]);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let router = Router::new().method("count", move || Ok(counter.fetch_add(1, Ordering::SeqCst) + 1));
+    let listener = TcpListener::bind(&addr, &handle).unwrap();
+    let handle2 = handle.clone();
+    let server = listener.incoming().for_each(move |(stream, _)| {
+        handle2.spawn(Server::new(router.clone(), stream).map_err(|_| ()));
+        Ok(())
+    });
+    handle.spawn(server.map_err(|e| panic!("{}", e)));
+    assert_eq!(core.run(kept).unwrap(), Ok(Value::from(1)));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
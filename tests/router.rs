@@ -0,0 +1,80 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+
+use framed_msgpack_rpc::message::RpcError;
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::Handler;
+use futures::Future;
+use futures::sync::oneshot;
+use rmpv::Value;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn router() -> Router {
+    Router::new()
+        .method("sayHello", |name: String| Ok(format!("Hello {}!", name)))
+        .method("add", |a: i64, b: i64| Ok(a + b))
+        .method("fail", || -> Result<(), Value> { Err(Value::from("failed")) })
+}
+
+#[test]
+fn dispatches_by_method() {
+    let mut router = router();
+    let hello = router.handle_request("sayHello", &[Value::from("World")]).wait().unwrap();
+    assert_eq!(hello, Ok(Value::from("Hello World!")));
+    let sum = router.handle_request("add", &[Value::from(2), Value::from(3)]).wait().unwrap();
+    assert_eq!(sum, Ok(Value::from(5)));
+    let fail = router.handle_request("fail", &[]).wait().unwrap();
+    assert_eq!(fail, Err(Value::from("failed")));
+}
+
+#[test]
+fn checks_params() {
+    let mut router = router();
+    assert!(router.handle_request("add", &[Value::from(2)]).wait().unwrap().is_err());
+    assert!(router.handle_request("add", &[Value::from(2), Value::from("3")]).wait().unwrap().is_err());
+    let error = router.handle_request("sayHello", &[]).wait().unwrap().unwrap_err();
+    assert_eq!(RpcError::try_from(error).unwrap().code, RpcError::INVALID_PARAMS);
+}
+
+#[test]
+fn method_not_found() {
+    let mut router = router();
+    let error = router.handle_request("sayGoodbye", &[]).wait().unwrap().unwrap_err();
+    assert_eq!(RpcError::try_from(error).unwrap().code, RpcError::METHOD_NOT_FOUND);
+}
+
+#[test]
+fn async_methods() {
+    let (tx, rx) = oneshot::channel::<i64>();
+    let rx = Mutex::new(Some(rx));
+    let mut router = Router::new()
+        .method_async("double", |a: i64| Ok::<_, Value>(a * 2))
+        .method_async("wait", move || {
+            rx.lock().unwrap().take().unwrap().map_err(|_| Value::from("canceled"))
+        });
+    let double = router.handle_request("double", &[Value::from(4)]).wait().unwrap();
+    assert_eq!(double, Ok(Value::from(8)));
+    let error = router.handle_request("double", &[Value::from("4")]).wait().unwrap().unwrap_err();
+    assert_eq!(RpcError::try_from(error).unwrap().code, RpcError::INVALID_PARAMS);
+
+    let waiting = router.handle_request("wait", &[]);
+    thread::spawn(move || tx.send(42));
+    assert_eq!(waiting.wait().unwrap(), Ok(Value::from(42)));
+}
+
+#[test]
+fn notifications_and_merge() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    let mut router = router().merge(Router::new().notification("log", move |msg: String| {
+        log.lock().unwrap().push(msg);
+    }));
+    router.handle_notification("log", &[Value::from("hello")]).wait().unwrap();
+    router.handle_notification("log", &[Value::from(1)]).wait().unwrap();
+    router.handle_notification("unknown", &[]).wait().unwrap();
+    assert_eq!(*received.lock().unwrap(), vec!["hello".to_owned()]);
+    assert!(router.handle_request("sayHello", &[Value::from("World")]).wait().unwrap().is_ok());
+}
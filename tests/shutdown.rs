@@ -0,0 +1,72 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+mod common;
+
+use framed_msgpack_rpc::client::{Client, RequestError};
+use framed_msgpack_rpc::server::{Handler, Server, Shutdown};
+use futures::{future, BoxFuture, Future, Stream};
+use rmpv::Value;
+use std::io;
+use std::time::Duration;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Core;
+
+#[derive(Clone)]
+struct SlowHandler {
+    shutdown: Shutdown,
+}
+
+impl Handler for SlowHandler {
+    type Error = io::Error;
+    type T = Value;
+    type E = Value;
+
+    fn handle_request(&mut self, method: &str, _params: &[Value]) -> BoxFuture<Result<Self::T, Self::E>, Self::Error> {
+        self.shutdown.shutdown(Duration::from_millis(500));
+        match method {
+            "slow" => common::delayed(Duration::from_millis(100), Ok(Value::from("done"))),
+            _ => Box::new(future::empty()),
+        }
+    }
+
+    fn handle_notification(&mut self, _method: &str, _params: &[Value]) -> BoxFuture<(), Self::Error> {
+        Box::new(future::ok(()))
+    }
+}
+
+fn run(method: &'static str) -> Result<Result<Value, Value>, RequestError> {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handler = SlowHandler { shutdown: Shutdown::new(&handle) };
+    let shutdown = handler.shutdown.clone();
+    let server = listener.incoming()
+        .into_future()
+        .map_err(|(e, _)| e)
+        .and_then(move |(stream, _)| {
+            let (stream, _) = stream.unwrap();
+            Server::new(handler, stream).with_shutdown(&shutdown)
+        });
+    let client = Client::connect(&addr, &handle)
+        .map_err(|_| ())
+        .and_then(move |client| client.request(method, &[]).then(Ok));
+    let (_, response) = core.run(server.map_err(|_| ()).join(client)).unwrap();
+    response
+}
+
+#[test]
+fn drains_pending_requests() {
+    assert_eq!(run("slow").unwrap(), Ok(Value::from("done")));
+}
+
+#[test]
+fn drops_requests_after_grace_period() {
+    match run("hang") {
+        Err(RequestError::ConnectionClosed) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+}
@@ -0,0 +1,98 @@
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tokio_core;
+
+mod common;
+
+use common::SleepHandler;
+use framed_msgpack_rpc::client::{Client, ClientBuilder, RequestError};
+use framed_msgpack_rpc::server::ServerBuilder;
+use futures::{future, Future, Stream};
+use futures::future::Either;
+use rmpv::Value;
+use std::time::Duration;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Timeout};
+
+/// Large enough for a few requests to fill the socket buffers.
+const PAYLOAD_LEN: usize = 1024 * 1024;
+
+fn assert_timed_out(result: Result<Result<Value, Value>, RequestError>) {
+    match result {
+        Err(RequestError::Timeout) => {}
+        r => panic!("Unexpected outcome: {:?}", r),
+    }
+}
+
+#[test]
+fn request_with_timeout_discards_late_response() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let addr = common::serve(&core, ServerBuilder::new(SleepHandler::new()));
+    let client = core.run(Client::connect(&addr, &handle)).unwrap();
+
+    let timed_out = client.request_with_timeout("sleep", &[Value::from(200)], Duration::from_millis(50));
+    assert_timed_out(core.run(timed_out));
+    assert_eq!(client.discarded_responses(), 0);
+
+    // The second request is answered after the late response to the first one has arrived.
+    let response = core.run(client.request("sleep", &[Value::from(300)])).unwrap();
+    assert_eq!(response, Ok(Value::from(300)));
+    assert_eq!(client.discarded_responses(), 1);
+}
+
+#[test]
+fn default_timeout_can_be_overridden() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let addr = common::serve(&core, ServerBuilder::new(SleepHandler::new()));
+    let connection = ClientBuilder::new().timeout(Duration::from_millis(50)).connect(&addr, &handle);
+    let client = core.run(connection).unwrap();
+
+    assert_timed_out(core.run(client.request("sleep", &[Value::from(200)])));
+    let response = client.request_with_timeout("sleep", &[Value::from(100)], Duration::from_secs(5));
+    assert_eq!(core.run(response).unwrap(), Ok(Value::from(100)));
+
+    // Let the late response arrive before checking that it was discarded.
+    core.run(Timeout::new(Duration::from_millis(200), &handle).unwrap()).unwrap();
+    assert_eq!(client.discarded_responses(), 1);
+}
+
+#[test]
+fn requests_time_out_when_the_server_stops_reading() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let addr = listener.local_addr().unwrap();
+    // The connection is accepted and kept open, but never read from.
+    let accepted = listener.incoming().into_future().map_err(|(e, _)| e);
+    let connection = ClientBuilder::new()
+        .capacity(2)
+        .timeout(Duration::from_millis(100))
+        .connect(&addr, &handle);
+    let ((_stream, _), client) = core.run(accepted.join(connection)).unwrap();
+
+    // The first requests fill the socket buffers, so the next ones are never written, and the
+    // last ones wait for room in the queue.
+    let payload = "x".repeat(PAYLOAD_LEN);
+    let responses: Vec<_> = (0..32)
+        .map(|i| {
+            let params = [Value::from(payload.as_str())];
+            let response = if i % 2 == 0 {
+                client.request("echo", &params)
+            } else {
+                client.request_with_timeout("echo", &params, Duration::from_millis(100))
+            };
+            response.then(Ok::<_, ()>)
+        })
+        .collect();
+    let guard = Timeout::new(Duration::from_secs(5), &handle).unwrap();
+    let results = match core.run(future::join_all(responses).select2(guard)) {
+        Ok(Either::A((results, _))) => results,
+        _ => panic!("The requests did not time out"),
+    };
+    for result in results {
+        assert_timed_out(result);
+    }
+}
@@ -0,0 +1,76 @@
+#![cfg(unix)]
+
+extern crate framed_msgpack_rpc;
+extern crate futures;
+extern crate rmpv;
+extern crate tempdir;
+extern crate tokio_core;
+extern crate tokio_uds;
+
+use framed_msgpack_rpc::UnframedCodec;
+use framed_msgpack_rpc::client::Client;
+use framed_msgpack_rpc::router::Router;
+use framed_msgpack_rpc::server::{self, Server, ServerBuilder};
+use futures::Future;
+use rmpv::Value;
+use tempdir::TempDir;
+use tokio_core::reactor::Core;
+use tokio_uds::UnixStream;
+
+fn router() -> Router {
+    Router::new().method("sayHello", |name: String| Ok(format!("Hello {}!", name)))
+}
+
+#[test]
+fn request_over_unix_socket() {
+    let dir = TempDir::new("framed-msgpack-rpc").unwrap();
+    let path = dir.path().join("server.sock");
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = server::serve_unix(&path, router(), &handle).unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+    let response = core.run(
+        Client::connect_unix(&path, &handle)
+            .map_err(|_| ())
+            .and_then(|client| client.request("sayHello", &[Value::from("World")]).map_err(|_| ()))
+    ).unwrap();
+    assert_eq!(response, Ok(Value::from("Hello World!")));
+}
+
+#[test]
+fn unframed_request_over_unix_socket() {
+    let dir = TempDir::new("framed-msgpack-rpc").unwrap();
+    let path = dir.path().join("server.sock");
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let listener = ServerBuilder::new(router())
+        .codec(UnframedCodec::new())
+        .bind_unix(&path, &handle)
+        .unwrap();
+    handle.spawn(listener.map_err(|e| panic!("{}", e)));
+    let response = core.run(
+        Client::connect_unix_with_codec(&path, UnframedCodec::new(), &handle)
+            .map_err(|_| ())
+            .and_then(|client| client.request("sayHello", &[Value::from("World")]).map_err(|_| ()))
+    ).unwrap();
+    assert_eq!(response, Ok(Value::from("Hello World!")));
+}
+
+#[test]
+fn connect_to_missing_socket_fails() {
+    let dir = TempDir::new("framed-msgpack-rpc").unwrap();
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    assert!(core.run(Client::connect_unix(dir.path().join("missing.sock"), &handle)).is_err());
+}
+
+#[test]
+fn client_from_socket_pair() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let (client_io, server_io) = UnixStream::pair(&handle).unwrap();
+    handle.spawn(Server::new(router(), server_io).map_err(|e| panic!("{}", e)));
+    let client = Client::from_io(client_io, &handle);
+    let response = core.run(client.request("sayHello", &[Value::from("World")])).unwrap();
+    assert_eq!(response, Ok(Value::from("Hello World!")));
+}